@@ -32,9 +32,26 @@ pub(crate) enum Output {
     Env,
     Table,
     TSV,
+    Value,
     None,
 }
 
+/// The formats secrets are exported to and imported from, as `KEY=value` pairs
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub(crate) enum FileFormat {
+    Dotenv,
+    Json,
+    Yaml,
+}
+
+/// What `secret import` does with keys that already exist with a different value
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub(crate) enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    Fail,
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "bws", version, about = "Bitwarden Secrets CLI", long_about = None)]
 pub(crate) struct Cli {
@@ -45,6 +62,14 @@ pub(crate) struct Cli {
     #[arg(short = 'o', long, global = true, value_enum, default_value_t = Output::JSON, help="Output format")]
     pub(crate) output: Output,
 
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "output",
+        help = "Print only the value of a single secret, same as --output value"
+    )]
+    pub(crate) raw: bool,
+
     #[arg(short = 'c', long, global = true, value_enum, default_value_t = Color::Auto, help="Use colors in the output")]
     pub(crate) color: Color,
 
@@ -91,19 +116,101 @@ pub(crate) enum Commands {
         #[command(subcommand)]
         cmd: SecretCommand,
     },
+    #[command(long_about = "Create and update projects and secrets to match a manifest file")]
+    Apply {
+        #[arg(
+            short = 'm',
+            long,
+            help = "The YAML manifest declaring projects and their secrets"
+        )]
+        manifest: PathBuf,
+
+        #[arg(long, help = "Make the changes instead of only showing the plan")]
+        apply: bool,
+
+        #[arg(
+            long,
+            help = "Delete secrets in the declared projects that aren't in the manifest"
+        )]
+        prune: bool,
+    },
+    #[command(long_about = "Commands available on templates")]
+    Template {
+        #[command(subcommand)]
+        cmd: TemplateCommand,
+    },
     #[command(long_about = "Run a command with secrets injected")]
     Run {
         #[arg(help = "The command to run")]
         command: Vec<String>,
         #[arg(long, help = "The shell to use")]
         shell: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "shell",
+            help = "Run the command directly instead of through a shell, keeping its arguments as given. Use -- before the command"
+        )]
+        no_shell: bool,
         #[arg(
             long,
             help = "Don't inherit environment variables from the current shell"
         )]
         no_inherit_env: bool,
-        #[arg(long, help = "The ID of the project to use")]
-        project_id: Option<Uuid>,
+        #[arg(
+            long,
+            help = "Replace secret values in the command's stdout and stderr with ***. The output is piped, so the command no longer sees a terminal"
+        )]
+        mask: bool,
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Check for changed secrets every SECONDS and restart the command when the injected ones change"
+        )]
+        watch: Option<u64>,
+        #[arg(
+            long,
+            value_name = "SIGNAL",
+            requires = "watch",
+            help = "Send SIGNAL (e.g. HUP) to the command instead of restarting it. Its environment doesn't change, so this is for programs that fetch secrets themselves"
+        )]
+        reload_signal: Option<String>,
+        #[arg(
+            long,
+            value_name = "SIGNAL",
+            requires = "watch",
+            conflicts_with = "reload_signal",
+            help = "The signal that stops the command before a restart [default: TERM]"
+        )]
+        restart_signal: Option<String>,
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            default_value_t = 10,
+            requires = "watch",
+            help = "How long the command has to exit after the restart signal before it's killed"
+        )]
+        grace_period: u64,
+        #[arg(
+            long = "project-id",
+            help = "The ID of a project to use secrets from, can be repeated"
+        )]
+        project_ids: Vec<Uuid>,
+        #[arg(
+            long = "secret-id",
+            help = "The ID of a secret to use, can be repeated"
+        )]
+        secret_ids: Vec<Uuid>,
+        #[arg(
+            long = "key",
+            help = "Only use the secret with this key, can be repeated"
+        )]
+        keys: Vec<String>,
+        #[arg(
+            long = "exclude-key",
+            help = "Don't use secrets whose key matches this glob (`*` and `?`), can be repeated"
+        )]
+        exclude_keys: Vec<String>,
         #[arg(
             long,
             global = true,
@@ -111,11 +218,33 @@ pub(crate) enum Commands {
             help = "Use the secret UUID (in its POSIX form) instead of the key name for the environment variable"
         )]
         uuids_as_keynames: bool,
+        #[arg(
+            long,
+            conflicts_with = "uuids_as_keynames",
+            help = "Convert keys to POSIX-compliant names, e.g. db-password to DB_PASSWORD"
+        )]
+        sanitize_keys: bool,
+        #[arg(long, help = "A prefix to add to every environment variable name")]
+        env_prefix: Option<String>,
+        #[arg(
+            long,
+            value_name = "KEY=ENVNAME",
+            help = "Use ENVNAME as the environment variable name for the secret KEY, can be repeated"
+        )]
+        map: Vec<String>,
     },
 }
 
 #[derive(Subcommand, Debug)]
 pub(crate) enum SecretCommand {
+    #[command(long_about = "Copy secrets into another project, keeping the originals")]
+    Copy {
+        #[arg(required = true)]
+        secret_ids: Vec<Uuid>,
+
+        #[arg(long, help = "The ID of the project to copy the secrets to")]
+        to: Uuid,
+    },
     Create {
         key: String,
         value: String,
@@ -127,11 +256,78 @@ pub(crate) enum SecretCommand {
         note: Option<String>,
     },
     Delete {
+        #[arg(required_unless_present = "key")]
         secret_ids: Vec<Uuid>,
+
+        #[arg(
+            long,
+            visible_alias = "by-key",
+            help = "Delete the secret with this key instead of by ID, can be repeated"
+        )]
+        key: Vec<String>,
+
+        #[arg(
+            long,
+            visible_alias = "in-project",
+            requires = "key",
+            help = "Only look for the keys in this project"
+        )]
+        project: Option<Uuid>,
+    },
+    #[command(long_about = "Compare the secrets of two projects by key")]
+    Diff {
+        #[arg(
+            long,
+            value_name = "[PROFILE:]PROJECT_ID",
+            help = "The project to compare from, optionally using a profile from the config file"
+        )]
+        from: String,
+
+        #[arg(
+            long,
+            value_name = "[PROFILE:]PROJECT_ID",
+            help = "The project to compare to, optionally using a profile from the config file"
+        )]
+        to: String,
+
+        #[arg(long, help = "Show the values instead of hashes of them")]
+        show_values: bool,
+
+        #[arg(
+            long,
+            env = "BWS_FROM_ACCESS_TOKEN",
+            hide_env_values = true,
+            help = "The access token for --from, if it's different from --access-token"
+        )]
+        from_access_token: Option<String>,
+
+        #[arg(
+            long,
+            env = "BWS_TO_ACCESS_TOKEN",
+            hide_env_values = true,
+            help = "The access token for --to, if it's different from --access-token"
+        )]
+        to_access_token: Option<String>,
     },
     #[clap(group = ArgGroup::new("edit_field").required(true).multiple(true))]
     Edit {
-        secret_id: Uuid,
+        #[arg(required_unless_present = "by_key")]
+        secret_id: Option<Uuid>,
+
+        #[arg(
+            long,
+            conflicts_with = "secret_id",
+            help = "Edit the secret with this key instead of by ID"
+        )]
+        by_key: Option<String>,
+
+        #[arg(
+            long,
+            requires = "by_key",
+            help = "Only look for the key in this project"
+        )]
+        in_project: Option<Uuid>,
+
         #[arg(long, group = "edit_field")]
         key: Option<String>,
         #[arg(long, group = "edit_field")]
@@ -141,16 +337,124 @@ pub(crate) enum SecretCommand {
         #[arg(long, group = "edit_field")]
         project_id: Option<Uuid>,
     },
+    #[command(long_about = "Export secrets to a dotenv, JSON or YAML file")]
+    Export {
+        #[arg(long, value_enum, default_value_t = FileFormat::Dotenv, help = "The file format")]
+        format: FileFormat,
+
+        #[arg(
+            long,
+            help = "Only export the secrets in this project, instead of all accessible secrets"
+        )]
+        project: Option<Uuid>,
+
+        #[arg(
+            short = 'O',
+            long,
+            help = "Write the secrets to this file instead of printing them. It's replaced atomically and only accessible by the current user"
+        )]
+        out_file: Option<PathBuf>,
+    },
     Get {
-        secret_id: Uuid,
+        #[arg(required_unless_present = "key")]
+        secret_id: Option<Uuid>,
+
+        #[arg(
+            long,
+            visible_alias = "by-key",
+            conflicts_with = "secret_id",
+            help = "Get the secret with this key instead of by ID"
+        )]
+        key: Option<String>,
+
+        #[arg(
+            long,
+            visible_alias = "in-project",
+            requires = "key",
+            help = "Only look for the key in this project"
+        )]
+        project: Option<Uuid>,
+    },
+    #[command(long_about = "Create or update secrets from a dotenv, JSON or YAML file")]
+    Import {
+        file: PathBuf,
+
+        #[arg(long, help = "The ID of the project to import the secrets into")]
+        project: Uuid,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "The file format [default: based on the file extension, dotenv otherwise]"
+        )]
+        format: Option<FileFormat>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = ConflictPolicy::Fail,
+            help = "What to do with keys that already exist in the project with a different value"
+        )]
+        on_conflict: ConflictPolicy,
+
+        #[arg(
+            long,
+            help = "Show what would be imported without changing any secrets"
+        )]
+        dry_run: bool,
     },
     List {
         project_id: Option<Uuid>,
     },
+    #[command(long_about = "Move secrets to another project")]
+    Move {
+        #[arg(required = true)]
+        secret_ids: Vec<Uuid>,
+
+        #[arg(long, help = "The ID of the project to move the secrets to")]
+        to: Uuid,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 pub(crate) enum ProjectCommand {
+    #[command(long_about = "Create a copy of a project with all of its secrets")]
+    Clone {
+        project_id: Uuid,
+
+        #[arg(long, help = "The name of the new project")]
+        name: String,
+
+        #[arg(
+            long = "include-key",
+            help = "Only copy secrets whose key matches this glob (`*` and `?`), can be repeated"
+        )]
+        include_keys: Vec<String>,
+
+        #[arg(
+            long = "exclude-key",
+            help = "Don't copy secrets whose key matches this glob (`*` and `?`), can be repeated"
+        )]
+        exclude_keys: Vec<String>,
+
+        #[arg(long, help = "Show what would be created without changing anything")]
+        dry_run: bool,
+
+        #[arg(
+            long,
+            value_name = "PROFILE",
+            help = "Create the copy with the server settings of this profile from the config file"
+        )]
+        to_profile: Option<String>,
+
+        #[arg(
+            long,
+            env = "BWS_TO_ACCESS_TOKEN",
+            hide_env_values = true,
+            help = "Create the copy with this access token, e.g. for another organization"
+        )]
+        to_access_token: Option<String>,
+    },
     Create {
         name: String,
     },
@@ -167,3 +471,23 @@ pub(crate) enum ProjectCommand {
     },
     List,
 }
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum TemplateCommand {
+    #[command(
+        long_about = "Replace {{ bws \"KEY\" }} and {{ bws_id \"<uuid>\" }} placeholders in a file with secret values"
+    )]
+    Render {
+        input: PathBuf,
+
+        #[arg(
+            short = 'O',
+            long,
+            help = "Write the result to this file instead of printing it. It's replaced atomically and only accessible by the current user"
+        )]
+        out_file: Option<PathBuf>,
+
+        #[arg(long, help = "Only look up keys in this project")]
+        project: Option<Uuid>,
+    },
+}
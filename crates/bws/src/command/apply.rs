@@ -0,0 +1,748 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use bitwarden::{
+    secrets_manager::{
+        projects::{ProjectCreateRequest, ProjectResponse, ProjectsListRequest},
+        secrets::{SecretCreateRequest, SecretPutRequest, SecretResponse, SecretsDeleteRequest},
+        ClientProjectsExt, ClientSecretsExt,
+    },
+    Client,
+};
+use color_eyre::eyre::{bail, Result};
+use itertools::Itertools;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    command::secret::{fetch_secrets, report_errors},
+    plan::{ChangeAction, PlannedChange},
+    render::{serialize_response, OutputSettings},
+};
+
+/// The projects and secrets a manifest declares
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    projects: Vec<ManifestProject>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestProject {
+    name: String,
+    #[serde(default)]
+    secrets: Vec<ManifestSecret>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "ManifestSecretFields")]
+struct ManifestSecret {
+    key: String,
+    source: ValueSource,
+    /// Existing notes are kept when this isn't set
+    note: Option<String>,
+}
+
+/// A secret as written in the manifest, before checking that it has exactly one source
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestSecretFields {
+    key: String,
+    value: Option<String>,
+    env: Option<String>,
+    file: Option<PathBuf>,
+    note: Option<String>,
+}
+
+impl TryFrom<ManifestSecretFields> for ManifestSecret {
+    type Error = String;
+
+    fn try_from(fields: ManifestSecretFields) -> Result<Self, Self::Error> {
+        let source = match (fields.value, fields.env, fields.file) {
+            (Some(value), None, None) => ValueSource::Value(value),
+            (None, Some(name), None) => ValueSource::Env(name),
+            (None, None, Some(path)) => ValueSource::File(path),
+            _ => {
+                return Err(format!(
+                    "The secret '{}' needs exactly one of value, env or file",
+                    fields.key
+                ))
+            }
+        };
+
+        Ok(ManifestSecret {
+            key: fields.key,
+            source,
+            note: fields.note,
+        })
+    }
+}
+
+/// Where the value of a secret comes from. Manifests don't hold values themselves, except for
+/// ones that aren't sensitive.
+#[derive(Debug)]
+enum ValueSource {
+    Value(String),
+    /// The name of an environment variable
+    Env(String),
+    /// A file relative to the manifest, without its trailing line break
+    File(PathBuf),
+}
+
+impl ValueSource {
+    fn read(&self, base_directory: &Path) -> Result<String> {
+        match self {
+            ValueSource::Value(value) => Ok(value.clone()),
+            ValueSource::Env(name) => match std::env::var(name) {
+                Ok(value) => Ok(value),
+                Err(_) => bail!("The environment variable {} is not set", name),
+            },
+            ValueSource::File(path) => {
+                let path = base_directory.join(path);
+                let text = match std::fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(e) => bail!("Could not read {}: {}", path.display(), e),
+                };
+                let value = text.strip_suffix('\n').unwrap_or(&text);
+                Ok(value.strip_suffix('\r').unwrap_or(value).to_string())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum SecretChange {
+    Create {
+        key: String,
+        value: String,
+        note: String,
+    },
+    Update {
+        id: Uuid,
+        key: String,
+        value: String,
+        note: String,
+    },
+    Delete {
+        id: Uuid,
+        key: String,
+    },
+}
+
+impl SecretChange {
+    fn key(&self) -> &str {
+        match self {
+            SecretChange::Create { key, .. }
+            | SecretChange::Update { key, .. }
+            | SecretChange::Delete { key, .. } => key,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ProjectPlan {
+    name: String,
+    /// `None` for projects that have to be created
+    id: Option<Uuid>,
+    changes: Vec<SecretChange>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ApplyCommandModel {
+    pub(crate) manifest: PathBuf,
+    pub(crate) apply: bool,
+    pub(crate) prune: bool,
+}
+
+pub(crate) async fn apply(
+    client: Client,
+    organization_id: Uuid,
+    command: ApplyCommandModel,
+    output_settings: OutputSettings,
+) -> Result<()> {
+    let manifest = read_manifest(&command.manifest)?;
+    let base_directory = match command.manifest.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let existing_projects = client
+        .projects()
+        .list(&ProjectsListRequest { organization_id })
+        .await?
+        .data;
+
+    let mut existing_secrets = HashMap::new();
+    for project in &existing_projects {
+        if manifest.projects.iter().any(|p| p.name == project.name) {
+            let secrets = fetch_secrets(&client, organization_id, Some(project.id)).await?;
+            existing_secrets.insert(project.id, secrets);
+        }
+    }
+
+    let plans = plan(
+        manifest,
+        base_directory,
+        &existing_projects,
+        &existing_secrets,
+        command.prune,
+    )?;
+
+    let planned_changes = to_planned_changes(&plans);
+    let change_count = planned_changes.len();
+    serialize_response(planned_changes, output_settings)?;
+
+    if change_count == 0 {
+        eprintln!("No changes, the projects match the manifest.");
+        return Ok(());
+    }
+    if !command.apply {
+        eprintln!("Run with --apply to make these changes.");
+        return Ok(());
+    }
+
+    // A failed change doesn't stop the others, except that a project that couldn't be created
+    // gets none of its secrets
+    let mut applied = 0;
+    let mut changes_failed = Vec::new();
+    let mut deleted = Vec::new();
+    for plan in plans {
+        let project_id = match plan.id {
+            Some(id) => id,
+            None => {
+                let result = client
+                    .projects()
+                    .create(&ProjectCreateRequest {
+                        organization_id,
+                        name: plan.name.clone(),
+                    })
+                    .await;
+                match result {
+                    Ok(project) => {
+                        applied += 1;
+                        project.id
+                    }
+                    Err(e) => {
+                        changes_failed.push((plan.name.clone(), e.to_string()));
+                        for change in &plan.changes {
+                            changes_failed.push((
+                                format!("{}/{}", plan.name, change.key()),
+                                "The project wasn't created".to_owned(),
+                            ));
+                        }
+                        continue;
+                    }
+                }
+            }
+        };
+
+        for change in plan.changes {
+            let label = format!("{}/{}", plan.name, change.key());
+            let result = match change {
+                SecretChange::Create { key, value, note } => client
+                    .secrets()
+                    .create(&SecretCreateRequest {
+                        organization_id,
+                        key,
+                        value,
+                        note,
+                        project_ids: Some(vec![project_id]),
+                    })
+                    .await
+                    .map(|_| ()),
+                SecretChange::Update {
+                    id,
+                    key,
+                    value,
+                    note,
+                } => client
+                    .secrets()
+                    .update(&SecretPutRequest {
+                        id,
+                        organization_id,
+                        key,
+                        value,
+                        note,
+                        project_ids: Some(vec![project_id]),
+                    })
+                    .await
+                    .map(|_| ()),
+                SecretChange::Delete { id, .. } => {
+                    deleted.push((id, label));
+                    continue;
+                }
+            };
+            match result {
+                Ok(()) => applied += 1,
+                Err(e) => changes_failed.push((label, e.to_string())),
+            }
+        }
+    }
+
+    if !deleted.is_empty() {
+        let ids = deleted.iter().map(|(id, _)| *id).collect();
+        match client.secrets().delete(SecretsDeleteRequest { ids }).await {
+            Ok(result) => {
+                for (id, label) in deleted {
+                    match result.data.iter().find(|r| r.id == id).map(|r| &r.error) {
+                        Some(None) => applied += 1,
+                        Some(Some(e)) => changes_failed.push((label, e.clone())),
+                        None => changes_failed.push((label, "Not deleted".to_owned())),
+                    }
+                }
+            }
+            Err(e) => {
+                for (_, label) in deleted {
+                    changes_failed.push((label, e.to_string()));
+                }
+            }
+        }
+    }
+
+    match changes_failed.len() {
+        0 => eprintln!("Applied {} changes.", applied),
+        _ => eprintln!("Applied {} of {} changes.", applied, change_count),
+    }
+
+    report_errors("apply", &changes_failed)
+}
+
+/// The changes that make the projects match `manifest`, given the existing projects and the
+/// secrets of the ones with a declared name. Nothing is sent to the server.
+fn plan(
+    manifest: Manifest,
+    base_directory: &Path,
+    existing_projects: &[ProjectResponse],
+    existing_secrets: &HashMap<Uuid, Vec<SecretResponse>>,
+    prune: bool,
+) -> Result<Vec<ProjectPlan>> {
+    let mut plans = Vec::new();
+    for project in manifest.projects {
+        let matching: Vec<_> = existing_projects
+            .iter()
+            .filter(|p| p.name == project.name)
+            .collect();
+        let project_id = match matching.as_slice() {
+            [] => None,
+            [existing] => Some(existing.id),
+            _ => bail!("Multiple projects are named '{}'", project.name),
+        };
+
+        let existing_secrets = project_id
+            .and_then(|id| existing_secrets.get(&id))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let mut changes = Vec::new();
+        for secret in &project.secrets {
+            let value = match secret.source.read(base_directory) {
+                Ok(value) => value,
+                Err(e) => bail!(
+                    "Could not get the value of '{}' in project '{}': {}",
+                    secret.key,
+                    project.name,
+                    e
+                ),
+            };
+
+            let matching: Vec<&SecretResponse> = existing_secrets
+                .iter()
+                .filter(|s| s.key == secret.key)
+                .collect();
+            match matching.as_slice() {
+                [] => changes.push(SecretChange::Create {
+                    key: secret.key.clone(),
+                    value,
+                    note: secret.note.clone().unwrap_or_default(),
+                }),
+                [existing] => {
+                    let note = secret.note.clone().unwrap_or_else(|| existing.note.clone());
+                    if existing.value != value || existing.note != note {
+                        changes.push(SecretChange::Update {
+                            id: existing.id,
+                            key: secret.key.clone(),
+                            value,
+                            note,
+                        });
+                    }
+                }
+                _ => bail!(
+                    "Multiple secrets in project '{}' have the key '{}'",
+                    project.name,
+                    secret.key
+                ),
+            }
+        }
+
+        if prune {
+            for existing in existing_secrets {
+                if !project.secrets.iter().any(|s| s.key == existing.key) {
+                    changes.push(SecretChange::Delete {
+                        id: existing.id,
+                        key: existing.key.clone(),
+                    });
+                }
+            }
+        }
+
+        plans.push(ProjectPlan {
+            name: project.name,
+            id: project_id,
+            changes,
+        });
+    }
+
+    Ok(plans)
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => bail!("Could not read {}: {}", path.display(), e),
+    };
+    let manifest: Manifest = match serde_yaml::from_str(&text) {
+        Ok(manifest) => manifest,
+        Err(e) => bail!("Invalid manifest {}: {}", path.display(), e),
+    };
+
+    if let Some(name) = manifest
+        .projects
+        .iter()
+        .map(|p| &p.name)
+        .duplicates()
+        .next()
+    {
+        bail!("The project '{}' is declared more than once", name);
+    }
+    for project in &manifest.projects {
+        if let Some(key) = project.secrets.iter().map(|s| &s.key).duplicates().next() {
+            bail!(
+                "The key '{}' is declared more than once in project '{}'",
+                key,
+                project.name
+            );
+        }
+    }
+
+    Ok(manifest)
+}
+
+fn to_planned_changes(plans: &[ProjectPlan]) -> Vec<PlannedChange> {
+    let mut planned_changes = Vec::new();
+    for plan in plans {
+        if plan.id.is_none() {
+            planned_changes.push(PlannedChange {
+                action: ChangeAction::Create,
+                project: plan.name.clone(),
+                key: None,
+            });
+        }
+
+        for change in &plan.changes {
+            let action = match change {
+                SecretChange::Create { .. } => ChangeAction::Create,
+                SecretChange::Update { .. } => ChangeAction::Update,
+                SecretChange::Delete { .. } => ChangeAction::Delete,
+            };
+            planned_changes.push(PlannedChange {
+                action,
+                project: plan.name.clone(),
+                key: Some(change.key().to_owned()),
+            });
+        }
+    }
+    planned_changes
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn manifest(yaml: &str) -> Manifest {
+        serde_yaml::from_str(yaml).expect("valid manifest")
+    }
+
+    fn project(name: &str) -> ProjectResponse {
+        ProjectResponse {
+            id: Uuid::new_v4(),
+            organization_id: Uuid::new_v4(),
+            name: name.to_string(),
+            creation_date: Utc::now(),
+            revision_date: Utc::now(),
+        }
+    }
+
+    fn secret(project: &ProjectResponse, key: &str, value: &str, note: &str) -> SecretResponse {
+        SecretResponse {
+            id: Uuid::new_v4(),
+            organization_id: project.organization_id,
+            project_id: Some(project.id),
+            key: key.to_string(),
+            value: value.to_string(),
+            note: note.to_string(),
+            creation_date: Utc::now(),
+            revision_date: Utc::now(),
+        }
+    }
+
+    fn summary(plans: &[ProjectPlan]) -> Vec<(ChangeAction, String, Option<String>)> {
+        to_planned_changes(plans)
+            .into_iter()
+            .map(|c| (c.action, c.project, c.key))
+            .collect()
+    }
+
+    const BACKEND: &str = r#"
+projects:
+  - name: backend
+    secrets:
+      - key: LOG_LEVEL
+        value: debug
+      - key: DB_HOST
+        value: db.internal
+      - key: DB_USER
+        value: app
+        note: Read only
+      - key: DB_NAME
+        value: app
+"#;
+
+    #[test]
+    fn test_plan_creates_missing_projects() {
+        let plans = plan(
+            manifest(BACKEND),
+            Path::new("."),
+            &[project("frontend")],
+            &HashMap::new(),
+            false,
+        )
+        .expect("valid plan");
+
+        let key = |k: &str| Some(k.to_string());
+        let project = "backend".to_string();
+        assert_eq!(
+            summary(&plans),
+            vec![
+                (ChangeAction::Create, project.clone(), None),
+                (ChangeAction::Create, project.clone(), key("LOG_LEVEL")),
+                (ChangeAction::Create, project.clone(), key("DB_HOST")),
+                (ChangeAction::Create, project.clone(), key("DB_USER")),
+                (ChangeAction::Create, project, key("DB_NAME")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_updates_changed_secrets() {
+        let backend = project("backend");
+        let existing = vec![
+            secret(&backend, "LOG_LEVEL", "debug", "Kept"),
+            secret(&backend, "DB_HOST", "db.old", "Kept"),
+            secret(&backend, "DB_USER", "app", "Old note"),
+            secret(&backend, "UNDECLARED", "1", ""),
+        ];
+        let host_id = existing[1].id;
+        let existing = HashMap::from([(backend.id, existing)]);
+
+        let plans = plan(
+            manifest(BACKEND),
+            Path::new("."),
+            &[backend],
+            &existing,
+            false,
+        )
+        .expect("valid plan");
+
+        let key = |k: &str| Some(k.to_string());
+        let project = "backend".to_string();
+        assert_eq!(
+            summary(&plans),
+            vec![
+                (ChangeAction::Update, project.clone(), key("DB_HOST")),
+                (ChangeAction::Update, project.clone(), key("DB_USER")),
+                (ChangeAction::Create, project, key("DB_NAME")),
+            ]
+        );
+
+        // Notes that the manifest doesn't set are kept
+        assert!(matches!(
+            &plans[0].changes[0],
+            SecretChange::Update { id, value, note, .. }
+                if *id == host_id && value == "db.internal" && note == "Kept"
+        ));
+        assert!(matches!(
+            &plans[0].changes[1],
+            SecretChange::Update { note, .. } if note == "Read only"
+        ));
+    }
+
+    #[test]
+    fn test_plan_prunes_undeclared_secrets() {
+        let backend = project("backend");
+        let undeclared = secret(&backend, "UNDECLARED", "1", "");
+        let undeclared_id = undeclared.id;
+        let existing = HashMap::from([(
+            backend.id,
+            vec![secret(&backend, "LOG_LEVEL", "debug", ""), undeclared],
+        )]);
+        let manifest = || {
+            manifest(
+                r#"
+projects:
+  - name: backend
+    secrets:
+      - key: LOG_LEVEL
+        value: debug
+"#,
+            )
+        };
+        let backend = [backend];
+
+        let plans = plan(manifest(), Path::new("."), &backend, &existing, false).unwrap();
+        assert!(summary(&plans).is_empty());
+
+        let plans = plan(manifest(), Path::new("."), &backend, &existing, true).unwrap();
+        assert_eq!(
+            summary(&plans),
+            vec![(
+                ChangeAction::Delete,
+                "backend".to_string(),
+                Some("UNDECLARED".to_string())
+            )]
+        );
+        assert!(matches!(
+            &plans[0].changes[0],
+            SecretChange::Delete { id, .. } if *id == undeclared_id
+        ));
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let duplicated = [project("backend"), project("backend")];
+        let error = plan(
+            manifest(BACKEND),
+            Path::new("."),
+            &duplicated,
+            &HashMap::new(),
+            false,
+        )
+        .expect_err("ambiguous project");
+        assert!(error.to_string().contains("Multiple projects"), "{error}");
+
+        let backend = project("backend");
+        let existing = HashMap::from([(
+            backend.id,
+            vec![
+                secret(&backend, "DB_HOST", "a", ""),
+                secret(&backend, "DB_HOST", "b", ""),
+            ],
+        )]);
+        let error = plan(
+            manifest(BACKEND),
+            Path::new("."),
+            &[backend],
+            &existing,
+            false,
+        )
+        .expect_err("ambiguous key");
+        assert!(
+            error.to_string().contains("have the key 'DB_HOST'"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest: Manifest = serde_yaml::from_str(
+            r#"
+projects:
+  - name: backend
+    secrets:
+      - key: LOG_LEVEL
+        value: debug
+      - key: DB_PASSWORD
+        env: DB_PASSWORD
+        note: Rotated monthly
+      - key: TLS_KEY
+        file: certs/tls.key
+  - name: empty
+"#,
+        )
+        .expect("valid manifest");
+
+        assert_eq!(manifest.projects.len(), 2);
+        let secrets = &manifest.projects[0].secrets;
+        assert!(matches!(&secrets[0].source, ValueSource::Value(v) if v == "debug"));
+        assert!(matches!(&secrets[1].source, ValueSource::Env(v) if v == "DB_PASSWORD"));
+        assert_eq!(secrets[1].note.as_deref(), Some("Rotated monthly"));
+        assert!(
+            matches!(&secrets[2].source, ValueSource::File(p) if p == Path::new("certs/tls.key"))
+        );
+        assert!(manifest.projects[1].secrets.is_empty());
+    }
+
+    #[test]
+    fn test_parse_manifest_without_value() {
+        let manifest = serde_yaml::from_str::<Manifest>(
+            r#"
+projects:
+  - name: backend
+    secrets:
+      - key: DB_PASSWORD
+"#,
+        );
+        assert!(manifest.is_err());
+    }
+
+    #[test]
+    fn test_parse_manifest_with_two_sources() {
+        let manifest = serde_yaml::from_str::<Manifest>(
+            r#"
+projects:
+  - name: backend
+    secrets:
+      - key: DB_PASSWORD
+        env: DB_PASSWORD
+        file: db_password.txt
+"#,
+        );
+        let error = manifest.expect_err("two sources").to_string();
+        assert!(
+            error.contains("exactly one of value, env or file"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_parse_manifest_with_unknown_field() {
+        let manifest = serde_yaml::from_str::<Manifest>(
+            r#"
+projects:
+  - name: backend
+    secrets:
+      - key: DB_PASSWORD
+        env: DB_PASSWORD
+        nte: Rotated monthly
+"#,
+        );
+        let error = manifest.expect_err("unknown field").to_string();
+        assert!(error.contains("nte"), "{error}");
+    }
+
+    #[test]
+    fn test_read_file_value() {
+        let directory = tempfile::tempdir().expect("temporary directory");
+        std::fs::write(directory.path().join("value.txt"), "s3cr3t\n").expect("writable file");
+
+        let source = ValueSource::File(PathBuf::from("value.txt"));
+        assert_eq!(
+            source.read(directory.path()).expect("readable file"),
+            "s3cr3t"
+        );
+    }
+}
@@ -1,6 +1,8 @@
+pub(crate) mod apply;
 pub(crate) mod project;
 pub(crate) mod run;
 pub(crate) mod secret;
+pub(crate) mod template;
 
 use std::{path::PathBuf, str::FromStr};
 
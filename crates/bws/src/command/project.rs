@@ -4,18 +4,32 @@ use bitwarden::{
             ProjectCreateRequest, ProjectGetRequest, ProjectPutRequest, ProjectsDeleteRequest,
             ProjectsListRequest,
         },
-        ClientProjectsExt,
+        secrets::SecretCreateRequest,
+        ClientProjectsExt, ClientSecretsExt,
     },
     Client,
 };
 use color_eyre::eyre::{bail, Result};
+use regex::Regex;
 use uuid::Uuid;
 
 use crate::{
+    command::secret::{fetch_secrets, report_results},
+    plan::{ChangeAction, PlannedChange},
     render::{serialize_response, OutputSettings},
+    util::glob_to_regex,
     ProjectCommand,
 };
 
+#[derive(Debug)]
+pub(crate) struct ProjectCloneCommandModel {
+    pub(crate) project_id: Uuid,
+    pub(crate) name: String,
+    pub(crate) include_keys: Vec<String>,
+    pub(crate) exclude_keys: Vec<String>,
+    pub(crate) dry_run: bool,
+}
+
 pub(crate) async fn process_command(
     command: ProjectCommand,
     client: Client,
@@ -32,6 +46,8 @@ pub(crate) async fn process_command(
             edit(client, organization_id, project_id, name, output_settings).await
         }
         ProjectCommand::Delete { project_ids } => delete(client, project_ids).await,
+        // Clone may log in again for the copy, so main handles it with the global settings
+        ProjectCommand::Clone { .. } => unreachable!(),
     }
 }
 
@@ -45,7 +61,7 @@ pub(crate) async fn list(
         .list(&ProjectsListRequest { organization_id })
         .await?
         .data;
-    serialize_response(projects, output_settings);
+    serialize_response(projects, output_settings)?;
 
     Ok(())
 }
@@ -59,7 +75,7 @@ pub(crate) async fn get(
         .projects()
         .get(&ProjectGetRequest { id: project_id })
         .await?;
-    serialize_response(project, output_settings);
+    serialize_response(project, output_settings)?;
 
     Ok(())
 }
@@ -77,7 +93,7 @@ pub(crate) async fn create(
             name,
         })
         .await?;
-    serialize_response(project, output_settings);
+    serialize_response(project, output_settings)?;
 
     Ok(())
 }
@@ -97,11 +113,88 @@ pub(crate) async fn edit(
             name,
         })
         .await?;
-    serialize_response(project, output_settings);
+    serialize_response(project, output_settings)?;
 
     Ok(())
 }
 
+/// Creates a project named `clone.name` with the secrets of `clone.project_id` whose keys pass
+/// the filters. `target` is the client and organization to create it with, when it's not the
+/// source's.
+pub(crate) async fn clone(
+    client: Client,
+    organization_id: Uuid,
+    target: Option<(Client, Uuid)>,
+    clone: ProjectCloneCommandModel,
+    output_settings: OutputSettings,
+) -> Result<()> {
+    let include = compile_globs(&clone.include_keys);
+    let exclude = compile_globs(&clone.exclude_keys);
+    let secrets: Vec<_> = fetch_secrets(&client, organization_id, Some(clone.project_id))
+        .await?
+        .into_iter()
+        .filter(|s| include.is_empty() || include.iter().any(|re| re.is_match(&s.key)))
+        .filter(|s| !exclude.iter().any(|re| re.is_match(&s.key)))
+        .collect();
+
+    if clone.dry_run {
+        let plan: Vec<_> = std::iter::once(None)
+            .chain(secrets.into_iter().map(|s| Some(s.key)))
+            .map(|key| PlannedChange {
+                action: ChangeAction::Create,
+                project: clone.name.clone(),
+                key,
+            })
+            .collect();
+        serialize_response(plan, output_settings)?;
+        eprintln!("Dry run, nothing was created.");
+        return Ok(());
+    }
+
+    let (client, organization_id) = target.unwrap_or((client, organization_id));
+
+    let project = client
+        .projects()
+        .create(&ProjectCreateRequest {
+            organization_id,
+            name: clone.name,
+        })
+        .await?;
+
+    let count = secrets.len();
+    let mut secrets_failed = Vec::new();
+    for secret in secrets {
+        let result = client
+            .secrets()
+            .create(&SecretCreateRequest {
+                organization_id,
+                key: secret.key.clone(),
+                value: secret.value,
+                note: secret.note,
+                project_ids: Some(vec![project.id]),
+            })
+            .await;
+        if let Err(e) = result {
+            secrets_failed.push((secret.key, e.to_string()));
+        }
+    }
+
+    let project_id = project.id;
+    serialize_response(project, output_settings)?;
+    if !secrets_failed.is_empty() {
+        eprintln!(
+            "The project {} was created without some of the secrets.",
+            project_id
+        );
+    }
+
+    report_results("copy", "copied", count, &secrets_failed)
+}
+
+fn compile_globs(globs: &[String]) -> Vec<Regex> {
+    globs.iter().map(|glob| glob_to_regex(glob)).collect()
+}
+
 pub(crate) async fn delete(client: Client, project_ids: Vec<Uuid>) -> Result<()> {
     let count = project_ids.len();
 
@@ -1,98 +1,330 @@
 use std::{
     collections::HashMap,
     io::{IsTerminal, Read},
-    process,
+    process::{self, ExitStatus},
+    time::Duration,
 };
 
 use bitwarden::{
     secrets_manager::{
-        secrets::{SecretIdentifiersByProjectRequest, SecretIdentifiersRequest, SecretsGetRequest},
+        secrets::{
+            SecretIdentifiersByProjectRequest, SecretIdentifiersRequest, SecretResponse,
+            SecretsGetRequest, SecretsSyncRequest,
+        },
         ClientSecretsExt,
     },
     Client,
 };
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{bail, Result};
 use itertools::Itertools;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    task::JoinHandle,
+    time::{Instant, Interval, MissedTickBehavior},
+};
 use uuid::Uuid;
 use which::which;
 
 use crate::{
-    util::{is_valid_posix_name, uuid_to_posix},
+    util::{glob_to_regex, is_valid_posix_name, to_posix_name, uuid_to_posix},
     ACCESS_TOKEN_KEY_VAR_NAME,
 };
 
 // Essential environment variables that should be preserved even when `--no-inherit-env` is used
 const WINDOWS_ESSENTIAL_VARS: &[&str] = &["SystemRoot", "ComSpec", "windir"];
 
+/// Which secrets `run` injects. Without projects or secret IDs every secret in the organization is
+/// considered, `keys` and `exclude_keys` then narrow that down.
+#[derive(Debug, Default)]
+pub(crate) struct SecretSelection {
+    pub(crate) project_ids: Vec<Uuid>,
+    pub(crate) secret_ids: Vec<Uuid>,
+    pub(crate) keys: Vec<String>,
+    pub(crate) exclude_keys: Vec<String>,
+}
+
+/// How secret keys become environment variable names. An explicit mapping wins over everything
+/// else, otherwise the prefix is added to the key, its POSIX form or the secret's UUID.
+#[derive(Debug, Default)]
+pub(crate) struct EnvNaming {
+    pub(crate) uuids_as_keynames: bool,
+    pub(crate) sanitize_keys: bool,
+    pub(crate) env_prefix: Option<String>,
+    /// `KEY=ENVNAME` pairs
+    pub(crate) map: Vec<String>,
+}
+
+impl EnvNaming {
+    fn mappings(&self) -> Result<HashMap<&str, &str>> {
+        self.map
+            .iter()
+            .map(|m| {
+                let Some((key, name)) = m.split_once('=') else {
+                    bail!("Invalid mapping '{}', expected KEY=ENVNAME", m);
+                };
+                if !is_valid_posix_name(name) {
+                    bail!(
+                        "Invalid mapping '{}', '{}' is not a POSIX-compliant name",
+                        m,
+                        name
+                    );
+                }
+                Ok((key, name))
+            })
+            .collect()
+    }
+
+    fn name(&self, mappings: &HashMap<&str, &str>, secret: &SecretResponse) -> String {
+        if let Some(name) = mappings.get(secret.key.as_str()) {
+            return name.to_string();
+        }
+
+        let name = if self.uuids_as_keynames {
+            uuid_to_posix(&secret.id)
+        } else if self.sanitize_keys {
+            to_posix_name(&secret.key)
+        } else {
+            secret.key.clone()
+        };
+
+        match &self.env_prefix {
+            Some(prefix) => format!("{prefix}{name}"),
+            None => name,
+        }
+    }
+}
+
+/// How the command is started and what happens to its output
+#[derive(Debug, Default)]
+pub(crate) struct ProcessOptions {
+    pub(crate) no_inherit_env: bool,
+    pub(crate) shell: Option<String>,
+    pub(crate) no_shell: bool,
+    /// Pipe the command's stdout and stderr through bws, replacing secret values with `***`
+    pub(crate) mask: bool,
+    /// Check for changed secrets this often, restarting or reloading the command when the injected
+    /// ones change
+    pub(crate) watch: Option<Duration>,
+    /// Sent to the command instead of restarting it. Its environment stays the same, so this is
+    /// for programs that fetch their secrets themselves.
+    pub(crate) reload_signal: Option<String>,
+    /// Sent to stop the command before a restart, `SIGTERM` if not set
+    pub(crate) restart_signal: Option<String>,
+    /// How long the command has to exit after the restart signal before it's killed
+    pub(crate) grace_period: Duration,
+}
+
 pub(crate) async fn run(
     client: Client,
     organization_id: Uuid,
-    project_id: Option<Uuid>,
-    uuids_as_keynames: bool,
-    no_inherit_env: bool,
-    shell: Option<String>,
+    selection: SecretSelection,
+    naming: EnvNaming,
+    options: ProcessOptions,
     command: Vec<String>,
 ) -> Result<i32> {
     let is_windows = std::env::consts::OS == "windows";
 
-    let shell = shell.unwrap_or_else(|| {
-        if is_windows {
-            "powershell".to_string()
-        } else {
-            "sh".to_string()
+    let (program, args) = if options.no_shell {
+        let Some((program, args)) = command.split_first() else {
+            bail!("No command provided");
+        };
+        if which(program).is_err() {
+            bail!("Command '{}' not found", program);
         }
-    });
 
-    if which(&shell).is_err() {
-        bail!("Shell '{}' not found", shell);
-    }
+        (program.clone(), args.to_vec())
+    } else {
+        let shell = options.shell.clone().unwrap_or_else(|| {
+            if is_windows {
+                "powershell".to_string()
+            } else {
+                "sh".to_string()
+            }
+        });
 
-    let user_command = if command.is_empty() {
-        if std::io::stdin().is_terminal() {
-            bail!("No command provided");
+        if which(&shell).is_err() {
+            bail!("Shell '{}' not found", shell);
         }
 
-        let mut buffer = String::new();
-        std::io::stdin().read_to_string(&mut buffer)?;
-        buffer
-    } else {
-        command.join(" ")
+        let user_command = if command.is_empty() {
+            if std::io::stdin().is_terminal() {
+                bail!("No command provided");
+            }
+
+            let mut buffer = String::new();
+            std::io::stdin().read_to_string(&mut buffer)?;
+            buffer
+        } else {
+            command.join(" ")
+        };
+
+        (shell, vec!["-c".to_string(), user_command])
     };
 
-    let res = if let Some(project_id) = project_id {
-        client
-            .secrets()
-            .list_by_project(&SecretIdentifiersByProjectRequest { project_id })
-            .await?
-    } else {
-        client
-            .secrets()
-            .list(&SecretIdentifiersRequest { organization_id })
-            .await?
+    let reload_signal = options
+        .reload_signal
+        .as_deref()
+        .map(parse_signal)
+        .transpose()?;
+    let restart_signal = options
+        .restart_signal
+        .as_deref()
+        .map(parse_signal)
+        .transpose()?;
+
+    // Taken before fetching, so changes made while fetching are picked up by the first sync
+    let mut last_synced_date = Utc::now();
+    let mut environment = load_environment(&client, organization_id, &selection, &naming).await?;
+
+    // Without a shell there's nothing left for bws to do, so the program replaces it and receives
+    // signals and reports its exit status directly. Masking and watching need bws to stay around.
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        if options.no_shell && !options.mask && options.watch.is_none() {
+            let e = build_command(&program, &args, &environment, &options, false).exec();
+            bail!("Failed to execute process: {}", e);
+        }
+    }
+
+    // Outside of a terminal (e.g. as PID 1 in a container) the child gets its own process group,
+    // so forwarded signals reach everything it started. In a terminal it stays in the foreground
+    // group, which keeps it able to read from the terminal.
+    let own_process_group = !std::io::stdin().is_terminal();
+
+    let spawn = |environment: &HashMap<String, String>| {
+        RunningChild::spawn(
+            build_command(&program, &args, environment, &options, own_process_group),
+            options.mask.then_some(environment),
+        )
     };
 
-    let secret_ids = res.data.into_iter().map(|e| e.id).collect();
-    let secrets = client
-        .secrets()
-        .get_by_ids(SecretsGetRequest { ids: secret_ids })
-        .await?
-        .data;
+    let mut signals = Signals::new()?;
+    let mut child = spawn(&environment)?;
+
+    let mut interval = options.watch.map(|period| {
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
+
+    loop {
+        tokio::select! {
+            status = child.wait() => {
+                let status = match status {
+                    Ok(status) => status,
+                    Err(e) => bail!("Failed to wait for process: {}", e),
+                };
+                child.finish().await?;
+
+                // propagate the exit status from the child process
+                return Ok(exit_code(status));
+            }
+            signal = signals.recv() => forward_signal(child.pid, signal, own_process_group),
+            _ = tick(&mut interval) => {
+                let changed = refresh_environment(
+                    &client,
+                    organization_id,
+                    &selection,
+                    &naming,
+                    &mut last_synced_date,
+                    &environment,
+                )
+                .await;
+                environment = match changed {
+                    Ok(Some(environment)) => environment,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        eprintln!("Warning: failed to check for changed secrets: {}", e);
+                        continue;
+                    }
+                };
+
+                match reload_signal {
+                    Some(signal) => {
+                        eprintln!("Secrets changed, signaling the command to reload");
+                        send_signal(child.pid, signal, own_process_group);
+                    }
+                    None => {
+                        eprintln!("Secrets changed, restarting the command");
+                        child
+                            .stop(restart_signal, options.grace_period, own_process_group)
+                            .await?;
+                        child = spawn(&environment)?;
+
+                        // Stopping can take the whole grace period, the next check counts from
+                        // the restart
+                        if let Some(interval) = interval.as_mut() {
+                            interval.reset();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fetches the selected secrets and names them, failing if names collide
+async fn load_environment(
+    client: &Client,
+    organization_id: Uuid,
+    selection: &SecretSelection,
+    naming: &EnvNaming,
+) -> Result<HashMap<String, String>> {
+    let secrets = select_secrets(client, organization_id, selection).await?;
+    name_secrets(secrets, naming)
+}
+
+/// Names the secrets as environment variables, failing if names collide
+fn name_secrets(
+    secrets: Vec<SecretResponse>,
+    naming: &EnvNaming,
+) -> Result<HashMap<String, String>> {
+    if let Some(prefix) = &naming.env_prefix {
+        if !is_valid_posix_name(prefix) {
+            bail!(
+                "Invalid --env-prefix '{}', it has to be a POSIX-compliant name",
+                prefix
+            );
+        }
+    }
 
-    if !uuids_as_keynames {
-        if let Some(duplicate) = secrets.iter().map(|s| &s.key).duplicates().next() {
-            bail!("Multiple secrets with name: '{}'. Use --uuids-as-keynames or use unique names for secrets", duplicate);
+    let mappings = naming.mappings()?;
+    for key in mappings.keys() {
+        if !secrets.iter().any(|s| s.key.as_str() == *key) {
+            eprintln!("Warning: no secret with the key '{}' to map", key);
         }
     }
 
-    let environment: HashMap<String, String> = secrets
+    let named_secrets: Vec<(String, SecretResponse)> = secrets
         .into_iter()
-        .map(|s| {
-            if uuids_as_keynames {
-                (uuid_to_posix(&s.id), s.value)
-            } else {
-                (s.key, s.value)
-            }
-        })
+        .map(|s| (naming.name(&mappings, &s), s))
+        .collect();
+
+    // Different keys can end up with the same name after mapping or sanitizing, so this checks the
+    // final names
+    if let Some(duplicate) = named_secrets
+        .iter()
+        .map(|(name, _)| name)
+        .duplicates()
+        .next()
+    {
+        let secrets = named_secrets
+            .iter()
+            .filter(|(name, _)| name == duplicate)
+            .map(|(_, s)| match s.project_id {
+                Some(project_id) => format!("'{}' in project {}", s.key, project_id),
+                None => format!("'{}' without a project", s.key),
+            })
+            .join(", ");
+        bail!("Multiple secrets with name: '{}' ({}). Use --uuids-as-keynames, --map, --exclude-key or use unique names for secrets", duplicate, secrets);
+    }
+
+    Ok(named_secrets
+        .into_iter()
+        .map(|(name, s)| (name, s.value))
         .inspect(|(k, _)| {
             if !is_valid_posix_name(k) {
                 eprintln!(
@@ -101,16 +333,62 @@ pub(crate) async fn run(
                 );
             }
         })
-        .collect();
+        .collect())
+}
 
-    let mut command = process::Command::new(shell);
-    command
-        .arg("-c")
-        .arg(&user_command)
-        .stdout(process::Stdio::inherit())
-        .stderr(process::Stdio::inherit());
+/// Asks the server whether any secrets changed since `last_synced_date`. Returns the new
+/// environment when that changed the injected secrets.
+async fn refresh_environment(
+    client: &Client,
+    organization_id: Uuid,
+    selection: &SecretSelection,
+    naming: &EnvNaming,
+    last_synced_date: &mut DateTime<Utc>,
+    current: &HashMap<String, String>,
+) -> Result<Option<HashMap<String, String>>> {
+    let synced_at = Utc::now();
+    let res = client
+        .secrets()
+        .sync(&SecretsSyncRequest {
+            organization_id,
+            last_synced_date: Some(*last_synced_date),
+        })
+        .await?;
 
-    if no_inherit_env {
+    if !res.has_changes {
+        *last_synced_date = synced_at;
+        return Ok(None);
+    }
+
+    let environment = load_environment(client, organization_id, selection, naming).await?;
+    *last_synced_date = synced_at;
+
+    Ok((environment != *current).then_some(environment))
+}
+
+fn build_command(
+    program: &str,
+    args: &[String],
+    environment: &HashMap<String, String>,
+    options: &ProcessOptions,
+    own_process_group: bool,
+) -> process::Command {
+    let is_windows = std::env::consts::OS == "windows";
+
+    let mut command = process::Command::new(program);
+    command.args(args);
+
+    if options.mask {
+        command
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped());
+    } else {
+        command
+            .stdout(process::Stdio::inherit())
+            .stderr(process::Stdio::inherit());
+    }
+
+    if options.no_inherit_env {
         let path = std::env::var("PATH").unwrap_or_else(|_| match is_windows {
             true => "C:\\Windows;C:\\Windows\\System32".to_string(),
             false => "/bin:/usr/bin".to_string(),
@@ -134,16 +412,546 @@ pub(crate) async fn run(
         command.envs(environment);
     }
 
-    // propagate the exit status from the child process
-    match command.spawn() {
-        Ok(mut child) => match child.wait() {
-            Ok(exit_status) => Ok(exit_status.code().unwrap_or(1)),
-            Err(e) => {
-                bail!("Failed to wait for process: {}", e)
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        if own_process_group {
+            command.process_group(0);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = own_process_group;
+
+    command
+}
+
+/// A started command, along with the tasks masking its output
+struct RunningChild {
+    child: tokio::process::Child,
+    pid: i32,
+    output_tasks: Vec<JoinHandle<std::io::Result<()>>>,
+}
+
+impl RunningChild {
+    /// Starts `command`. With `mask_values` its output is piped through a [SecretMasker].
+    fn spawn(
+        command: process::Command,
+        mask_values: Option<&HashMap<String, String>>,
+    ) -> Result<Self> {
+        let mut child = match tokio::process::Command::from(command).spawn() {
+            Ok(child) => child,
+            Err(e) => bail!("Failed to execute process: {}", e),
+        };
+        let Some(pid) = child.id().and_then(|id| i32::try_from(id).ok()) else {
+            bail!("Child process has no process ID");
+        };
+
+        let mut output_tasks = Vec::new();
+        if let Some(environment) = mask_values {
+            let values: Vec<&str> = environment.values().map(String::as_str).collect();
+            if let Some(stdout) = child.stdout.take() {
+                let masker = SecretMasker::new(values.iter().copied());
+                output_tasks.push(tokio::spawn(mask_output(
+                    stdout,
+                    tokio::io::stdout(),
+                    masker,
+                )));
+            }
+            if let Some(stderr) = child.stderr.take() {
+                let masker = SecretMasker::new(values.iter().copied());
+                output_tasks.push(tokio::spawn(mask_output(
+                    stderr,
+                    tokio::io::stderr(),
+                    masker,
+                )));
+            }
+        }
+
+        Ok(RunningChild {
+            child,
+            pid,
+            output_tasks,
+        })
+    }
+
+    async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        self.child.wait().await
+    }
+
+    /// Waits for the masked output to be written. The pipes close once the child and anything it
+    /// started are gone.
+    async fn finish(self) -> Result<()> {
+        for task in self.output_tasks {
+            if let Ok(Err(e)) = task.await {
+                bail!("Failed to write process output: {}", e);
             }
-        },
-        Err(e) => {
-            bail!("Failed to execute process: {}", e)
         }
+
+        Ok(())
+    }
+
+    /// Stops the command for a restart with `signal`, killing it if it's still running after the
+    /// grace period. Without signals, on Windows, it's killed right away.
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    async fn stop(
+        mut self,
+        signal: Option<i32>,
+        grace_period: Duration,
+        own_process_group: bool,
+    ) -> Result<()> {
+        #[cfg(unix)]
+        send_signal(self.pid, signal.unwrap_or(libc::SIGTERM), own_process_group);
+        #[cfg(not(unix))]
+        self.child.start_kill()?;
+
+        if tokio::time::timeout(grace_period, self.child.wait())
+            .await
+            .is_err()
+        {
+            eprintln!("Warning: the command didn't exit within the grace period, killing it");
+            #[cfg(unix)]
+            send_signal(self.pid, libc::SIGKILL, own_process_group);
+            #[cfg(not(unix))]
+            self.child.start_kill()?;
+
+            self.child.wait().await?;
+        }
+
+        self.finish().await
+    }
+}
+
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+const MASK: &[u8] = b"***";
+
+/// Replaces secret values in a stream of output with `***`. Output that could be the start of a
+/// value is held back until the following chunk shows whether it is one, so values split across
+/// reads are masked too.
+struct SecretMasker {
+    /// Longest first, so a value containing another one is masked as a whole
+    values: Vec<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl SecretMasker {
+    fn new<'a>(values: impl IntoIterator<Item = &'a str>) -> Self {
+        let values = values
+            .into_iter()
+            .filter(|v| !v.is_empty())
+            .map(|v| v.as_bytes().to_vec())
+            .unique()
+            .sorted_by_key(|v| std::cmp::Reverse(v.len()))
+            .collect();
+
+        SecretMasker {
+            values,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Masks the next chunk of output, returning what can be written so far
+    fn mask(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        self.process(false)
+    }
+
+    /// Masks whatever was held back, at the end of the output
+    fn finish(&mut self) -> Vec<u8> {
+        self.process(true)
+    }
+
+    fn process(&mut self, end: bool) -> Vec<u8> {
+        let input = std::mem::take(&mut self.pending);
+        let mut output = Vec::with_capacity(input.len());
+
+        let mut i = 0;
+        while i < input.len() {
+            let rest = &input[i..];
+
+            if !end
+                && self
+                    .values
+                    .iter()
+                    .any(|v| v.len() > rest.len() && v.starts_with(rest))
+            {
+                self.pending = rest.to_vec();
+                break;
+            }
+
+            match self.values.iter().find(|v| rest.starts_with(v)) {
+                Some(value) => {
+                    output.extend_from_slice(MASK);
+                    i += value.len();
+                }
+                None => {
+                    output.push(rest[0]);
+                    i += 1;
+                }
+            }
+        }
+
+        output
+    }
+}
+
+/// Copies the child's output from `reader` to `writer` through `masker`
+async fn mask_output(
+    mut reader: impl AsyncRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+    mut masker: SecretMasker,
+) -> std::io::Result<()> {
+    let mut buffer = [0; 8192];
+
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        let output = match read {
+            0 => masker.finish(),
+            n => masker.mask(&buffer[..n]),
+        };
+
+        writer.write_all(&output).await?;
+        writer.flush().await?;
+
+        if read == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// The exit code of the command. A child killed by a signal results in the conventional
+/// `128 + signal`.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let (None, Some(signal)) = (status.code(), status.signal()) {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+/// The termination signals bws forwards to the command
+#[cfg(unix)]
+struct Signals {
+    terminate: tokio::signal::unix::Signal,
+    interrupt: tokio::signal::unix::Signal,
+    hangup: tokio::signal::unix::Signal,
+    quit: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        Ok(Signals {
+            terminate: signal(SignalKind::terminate())?,
+            interrupt: signal(SignalKind::interrupt())?,
+            hangup: signal(SignalKind::hangup())?,
+            quit: signal(SignalKind::quit())?,
+        })
+    }
+
+    async fn recv(&mut self) -> i32 {
+        tokio::select! {
+            _ = self.terminate.recv() => libc::SIGTERM,
+            _ = self.interrupt.recv() => libc::SIGINT,
+            _ = self.hangup.recv() => libc::SIGHUP,
+            _ = self.quit.recv() => libc::SIGQUIT,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct Signals;
+
+#[cfg(not(unix))]
+impl Signals {
+    fn new() -> Result<Self> {
+        Ok(Signals)
+    }
+
+    async fn recv(&mut self) -> i32 {
+        std::future::pending().await
+    }
+}
+
+/// Parses a signal name like `HUP` or `SIGHUP`
+#[cfg(unix)]
+fn parse_signal(name: &str) -> Result<i32> {
+    let upper = name.to_uppercase();
+    Ok(match upper.strip_prefix("SIG").unwrap_or(upper.as_str()) {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "TERM" => libc::SIGTERM,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        _ => bail!(
+            "Unsupported signal '{}', expected one of HUP, INT, QUIT, TERM, USR1 or USR2",
+            name
+        ),
+    })
+}
+
+#[cfg(not(unix))]
+fn parse_signal(name: &str) -> Result<i32> {
+    bail!("Signals like '{}' are only supported on Unix", name);
+}
+
+/// Forwards a signal bws received to the command. The terminal already delivered interrupts
+/// typed in it to the command when it shares the terminal's group, so those aren't sent a
+/// second time.
+#[cfg(unix)]
+fn forward_signal(pid: i32, signal: i32, own_process_group: bool) {
+    if !own_process_group && matches!(signal, libc::SIGINT | libc::SIGQUIT) {
+        return;
+    }
+
+    send_signal(pid, signal, own_process_group);
+}
+
+/// Sends `signal` to the child's process group, or to the child alone when it shares the
+/// terminal's group
+#[cfg(unix)]
+fn send_signal(pid: i32, signal: i32, own_process_group: bool) {
+    let target = match own_process_group {
+        true => -pid,
+        false => pid,
+    };
+
+    // SAFETY: kill doesn't access memory, it fails harmlessly if the process is already gone
+    unsafe {
+        libc::kill(target, signal);
+    }
+}
+
+// Signals are never received or parsed on other platforms, so there's nothing to send
+#[cfg(not(unix))]
+fn forward_signal(_pid: i32, _signal: i32, _own_process_group: bool) {}
+
+#[cfg(not(unix))]
+fn send_signal(_pid: i32, _signal: i32, _own_process_group: bool) {}
+
+/// Fetches the secrets described by `selection`, failing if an explicitly requested one is missing
+async fn select_secrets(
+    client: &Client,
+    organization_id: Uuid,
+    selection: &SecretSelection,
+) -> Result<Vec<SecretResponse>> {
+    let mut secret_ids = selection.secret_ids.clone();
+
+    if selection.project_ids.is_empty() && selection.secret_ids.is_empty() {
+        let res = client
+            .secrets()
+            .list(&SecretIdentifiersRequest { organization_id })
+            .await?;
+        secret_ids.extend(res.data.into_iter().map(|e| e.id));
+    }
+
+    for &project_id in &selection.project_ids {
+        let res = client
+            .secrets()
+            .list_by_project(&SecretIdentifiersByProjectRequest { project_id })
+            .await?;
+        secret_ids.extend(res.data.into_iter().map(|e| e.id));
+    }
+
+    let secret_ids: Vec<Uuid> = secret_ids.into_iter().unique().collect();
+    let secrets = match secret_ids.is_empty() {
+        true => Vec::new(),
+        false => {
+            client
+                .secrets()
+                .get_by_ids(SecretsGetRequest { ids: secret_ids })
+                .await?
+                .data
+        }
+    };
+
+    if let Some(missing) = selection
+        .secret_ids
+        .iter()
+        .find(|id| !secrets.iter().any(|s| &s.id == *id))
+    {
+        bail!("Secret '{}' not found", missing);
+    }
+    if let Some(missing) = selection
+        .keys
+        .iter()
+        .find(|key| !secrets.iter().any(|s| &s.key == *key))
+    {
+        bail!("No secret with the key '{}' found", missing);
+    }
+
+    let exclude_keys: Vec<_> = selection
+        .exclude_keys
+        .iter()
+        .map(|glob| glob_to_regex(glob.as_str()))
+        .collect();
+
+    Ok(secrets
+        .into_iter()
+        .filter(|s| selection.keys.is_empty() || selection.keys.contains(&s.key))
+        .filter(|s| !exclude_keys.iter().any(|re| re.is_match(&s.key)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask_chunks(values: &[&str], chunks: &[&str]) -> String {
+        let mut masker = SecretMasker::new(values.iter().copied());
+        let mut output = Vec::new();
+        for chunk in chunks {
+            output.extend(masker.mask(chunk.as_bytes()));
+        }
+        output.extend(masker.finish());
+        String::from_utf8(output).expect("valid UTF-8")
+    }
+
+    #[test]
+    fn test_mask_values() {
+        assert_eq!(
+            mask_chunks(
+                &["hunter2", "s3cr3t"],
+                &["password=hunter2, token=s3cr3t\n"]
+            ),
+            "password=***, token=***\n"
+        );
+    }
+
+    #[test]
+    fn test_mask_values_split_across_chunks() {
+        assert_eq!(
+            mask_chunks(&["hunter2"], &["password=hun", "t", "er2 done"]),
+            "password=*** done"
+        );
+        assert_eq!(mask_chunks(&["hunter2"], &["hunt", "ing"]), "hunting");
+    }
+
+    #[test]
+    fn test_mask_holds_back_only_possible_values() {
+        let mut masker = SecretMasker::new(["hunter2"]);
+        assert_eq!(masker.mask(b"ok, hun"), b"ok, ");
+        assert_eq!(masker.mask(b"gry"), b"hungry");
+        assert_eq!(masker.finish(), b"");
+    }
+
+    #[test]
+    fn test_mask_prefers_longest_value() {
+        assert_eq!(
+            mask_chunks(&["abc", "abcdef"], &["xabcdefx abcx"]),
+            "x***x ***x"
+        );
+        assert_eq!(mask_chunks(&["abc", "abcdef"], &["xabc"]), "x***");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("HUP").expect("valid signal"), libc::SIGHUP);
+        assert_eq!(
+            parse_signal("sigusr1").expect("valid signal"),
+            libc::SIGUSR1
+        );
+        assert!(parse_signal("SIGFOO").is_err());
+    }
+
+    #[test]
+    fn test_mask_ignores_empty_values() {
+        assert_eq!(mask_chunks(&[""], &["nothing to hide"]), "nothing to hide");
+    }
+
+    fn secret(key: &str, value: &str) -> SecretResponse {
+        SecretResponse {
+            id: Uuid::new_v4(),
+            organization_id: Uuid::new_v4(),
+            project_id: None,
+            key: key.to_string(),
+            value: value.to_string(),
+            note: String::new(),
+            creation_date: Utc::now(),
+            revision_date: Utc::now(),
+        }
+    }
+
+    fn naming(sanitize_keys: bool, env_prefix: Option<&str>, map: &[&str]) -> EnvNaming {
+        EnvNaming {
+            uuids_as_keynames: false,
+            sanitize_keys,
+            env_prefix: env_prefix.map(str::to_string),
+            map: map.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_name_secrets_with_prefix() {
+        let secrets = vec![secret("DB_PASSWORD", "1"), secret("API_KEY", "2")];
+
+        let env = name_secrets(secrets, &naming(false, Some("APP_"), &["API_KEY=TOKEN"])).unwrap();
+
+        assert_eq!(env.get("APP_DB_PASSWORD").map(String::as_str), Some("1"));
+        // Mapped names are used as given
+        assert_eq!(env.get("TOKEN").map(String::as_str), Some("2"));
+        assert_eq!(env.len(), 2);
+    }
+
+    #[test]
+    fn test_name_secrets_sanitized() {
+        let secrets = vec![secret("db-password", "1"), secret("2fa.code", "2")];
+
+        let env = name_secrets(secrets, &naming(true, Some("APP_"), &[])).unwrap();
+
+        assert_eq!(env.get("APP_DB_PASSWORD").map(String::as_str), Some("1"));
+        assert_eq!(env.get("APP__2FA_CODE").map(String::as_str), Some("2"));
+    }
+
+    #[test]
+    fn test_name_secrets_uuids_as_keynames() {
+        let secret = secret("key", "value");
+        let naming = EnvNaming {
+            uuids_as_keynames: true,
+            ..naming(false, None, &[])
+        };
+
+        let name = uuid_to_posix(&secret.id);
+
+        let env = name_secrets(vec![secret], &naming).unwrap();
+
+        assert_eq!(env.get(&name).map(String::as_str), Some("value"));
+    }
+
+    #[test]
+    fn test_name_secrets_collisions() {
+        let sanitized = vec![secret("db-password", "1"), secret("DB_PASSWORD", "2")];
+        let error = name_secrets(sanitized, &naming(true, None, &[])).unwrap_err();
+        assert!(error.to_string().contains("'DB_PASSWORD'"), "{error}");
+
+        let mapped = vec![secret("A", "1"), secret("B", "2")];
+        assert!(name_secrets(mapped, &naming(false, None, &["A=B"])).is_err());
+    }
+
+    #[test]
+    fn test_name_secrets_invalid_options() {
+        let secrets = || vec![secret("KEY", "value")];
+
+        assert!(name_secrets(secrets(), &naming(false, Some("1-"), &[])).is_err());
+        assert!(name_secrets(secrets(), &naming(false, Some("APP-"), &[])).is_err());
+        assert!(name_secrets(secrets(), &naming(false, None, &["KEY"])).is_err());
+        assert!(name_secrets(secrets(), &naming(false, None, &["KEY=1-X"])).is_err());
     }
 }
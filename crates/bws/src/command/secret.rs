@@ -1,3 +1,10 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Display},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
 use bitwarden::{
     secrets_manager::{
         secrets::{
@@ -10,11 +17,20 @@ use bitwarden::{
     Client,
 };
 use color_eyre::eyre::{bail, Result};
+use hmac::{Hmac, Mac};
+use itertools::Itertools;
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use sha2::Sha256;
 use uuid::Uuid;
 
 use crate::{
+    dotenv,
     render::{serialize_response, OutputSettings},
-    SecretCommand,
+    util::write_private_file,
+    ConflictPolicy, FileFormat, SecretCommand,
 };
 
 #[derive(Debug)]
@@ -34,6 +50,81 @@ pub(crate) struct SecretEditCommandModel {
     pub(crate) project_id: Option<Uuid>,
 }
 
+#[derive(Debug)]
+pub(crate) struct SecretImportCommandModel {
+    pub(crate) file: PathBuf,
+    pub(crate) project_id: Uuid,
+    pub(crate) format: Option<FileFormat>,
+    pub(crate) on_conflict: ConflictPolicy,
+    pub(crate) dry_run: bool,
+}
+
+/// What `import` does, or did with `--dry-run`, with a key from the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ImportAction {
+    Create,
+    Update,
+    Skip,
+    Unchanged,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImportResult {
+    pub(crate) key: String,
+    pub(crate) action: ImportAction,
+    /// Not known for secrets that would be created by a dry run
+    pub(crate) id: Option<Uuid>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SecretDiffCommandModel {
+    pub(crate) from: String,
+    pub(crate) to: String,
+    pub(crate) show_values: bool,
+    pub(crate) from_access_token: Option<String>,
+    pub(crate) to_access_token: Option<String>,
+    pub(crate) access_token: String,
+    pub(crate) server_url: Option<String>,
+    pub(crate) profile: Option<String>,
+    pub(crate) config_file: Option<PathBuf>,
+}
+
+/// The login for one side of a diff that doesn't use the current client
+#[derive(Debug, PartialEq, Eq)]
+struct DiffSideLogin {
+    access_token: String,
+    server_url: Option<String>,
+    profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DiffStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SecretDiff {
+    pub(crate) key: String,
+    pub(crate) status: DiffStatus,
+    /// The value, or a hash of it, on the `--from` side
+    pub(crate) from: Option<String>,
+    /// The value, or a hash of it, on the `--to` side
+    pub(crate) to: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Transfer {
+    Move,
+    Copy,
+}
+
 pub(crate) async fn process_command(
     command: SecretCommand,
     client: Client,
@@ -44,7 +135,45 @@ pub(crate) async fn process_command(
         SecretCommand::List { project_id } => {
             list(client, organization_id, project_id, output_settings).await
         }
-        SecretCommand::Get { secret_id } => get(client, secret_id, output_settings).await,
+        SecretCommand::Get {
+            secret_id,
+            key,
+            project,
+        } => match (secret_id, key) {
+            (Some(secret_id), _) => get(client, secret_id, output_settings).await,
+            (None, Some(key)) => {
+                let secret = find_by_key(&client, organization_id, &key, project).await?;
+                serialize_response(secret, output_settings)?;
+                Ok(())
+            }
+            (None, None) => bail!("Missing secret ID or key"),
+        },
+        SecretCommand::Export {
+            format,
+            project,
+            out_file,
+        } => export(client, organization_id, project, format, out_file).await,
+        SecretCommand::Import {
+            file,
+            project,
+            format,
+            on_conflict,
+            dry_run,
+        } => {
+            import(
+                client,
+                organization_id,
+                SecretImportCommandModel {
+                    file,
+                    project_id: project,
+                    format,
+                    on_conflict,
+                    dry_run,
+                },
+                output_settings,
+            )
+            .await
+        }
         SecretCommand::Create {
             key,
             value,
@@ -66,11 +195,22 @@ pub(crate) async fn process_command(
         }
         SecretCommand::Edit {
             secret_id,
+            by_key,
+            in_project,
             key,
             value,
             note,
             project_id,
         } => {
+            let secret_id = match (secret_id, by_key) {
+                (Some(secret_id), _) => secret_id,
+                (None, Some(by_key)) => {
+                    find_by_key(&client, organization_id, &by_key, in_project)
+                        .await?
+                        .id
+                }
+                (None, None) => bail!("Missing secret ID or key"),
+            };
             edit(
                 client,
                 organization_id,
@@ -85,7 +225,87 @@ pub(crate) async fn process_command(
             )
             .await
         }
-        SecretCommand::Delete { secret_ids } => delete(client, secret_ids).await,
+        SecretCommand::Delete {
+            mut secret_ids,
+            key,
+            project,
+        } => {
+            for key in key {
+                secret_ids.push(
+                    find_by_key(&client, organization_id, &key, project)
+                        .await?
+                        .id,
+                );
+            }
+            delete(client, secret_ids).await
+        }
+        SecretCommand::Copy { secret_ids, to } => {
+            transfer(client, organization_id, secret_ids, to, Transfer::Copy).await
+        }
+        SecretCommand::Move { secret_ids, to } => {
+            transfer(client, organization_id, secret_ids, to, Transfer::Move).await
+        }
+        // Diff may log in with other profiles, so main handles it with the global settings
+        SecretCommand::Diff { .. } => unreachable!(),
+    }
+}
+
+/// Finds the only secret with the given key, in the whole organization or in `project_id`. Zero
+/// or multiple matches are errors, as keys aren't unique.
+pub(crate) async fn find_by_key(
+    client: &Client,
+    organization_id: Uuid,
+    key: &str,
+    project_id: Option<Uuid>,
+) -> Result<SecretResponse> {
+    let identifiers = if let Some(project_id) = project_id {
+        client
+            .secrets()
+            .list_by_project(&SecretIdentifiersByProjectRequest { project_id })
+            .await?
+    } else {
+        client
+            .secrets()
+            .list(&SecretIdentifiersRequest { organization_id })
+            .await?
+    };
+
+    let secret_ids: Vec<Uuid> = identifiers
+        .data
+        .into_iter()
+        .filter(|s| s.key == key)
+        .map(|s| s.id)
+        .collect();
+    if secret_ids.is_empty() {
+        match project_id {
+            Some(project_id) => bail!("No secret with the key \"{key}\" in project {project_id}"),
+            None => bail!("No secret with the key \"{key}\""),
+        }
+    }
+
+    let mut secrets = client
+        .secrets()
+        .get_by_ids(SecretsGetRequest { ids: secret_ids })
+        .await?
+        .data;
+
+    match secrets.len() {
+        0 => bail!("No secret with the key \"{key}\""),
+        1 => Ok(secrets.remove(0)),
+        n => {
+            let matches: Vec<String> = secrets
+                .iter()
+                .map(|s| match s.project_id {
+                    Some(project_id) => format!("  {} (project {})", s.id, project_id),
+                    None => format!("  {} (no project)", s.id),
+                })
+                .collect();
+            bail!(
+                "{n} secrets have the key \"{key}\", use a secret ID or limit the search to a \
+                 project:\n{}",
+                matches.join("\n")
+            )
+        }
     }
 }
 
@@ -95,6 +315,18 @@ pub(crate) async fn list(
     project_id: Option<Uuid>,
     output_settings: OutputSettings,
 ) -> Result<()> {
+    let secrets = fetch_secrets(&client, organization_id, project_id).await?;
+    serialize_response(secrets, output_settings)?;
+
+    Ok(())
+}
+
+/// Fetches the secrets in `project_id`, or all accessible secrets in the organization
+pub(crate) async fn fetch_secrets(
+    client: &Client,
+    organization_id: Uuid,
+    project_id: Option<Uuid>,
+) -> Result<Vec<SecretResponse>> {
     let res = if let Some(project_id) = project_id {
         client
             .secrets()
@@ -108,21 +340,394 @@ pub(crate) async fn list(
     };
 
     if res.data.is_empty() {
-        serialize_response(Vec::<SecretResponse>::new(), output_settings);
-        return Ok(());
+        return Ok(Vec::new());
     }
 
     let secret_ids = res.data.into_iter().map(|e| e.id).collect();
-    let secrets = client
+    Ok(client
         .secrets()
         .get_by_ids(SecretsGetRequest { ids: secret_ids })
         .await?
-        .data;
-    serialize_response(secrets, output_settings);
+        .data)
+}
+
+pub(crate) async fn export(
+    client: Client,
+    organization_id: Uuid,
+    project_id: Option<Uuid>,
+    format: FileFormat,
+    out_file: Option<PathBuf>,
+) -> Result<()> {
+    let secrets = fetch_secrets(&client, organization_id, project_id).await?;
+
+    let mut values = BTreeMap::new();
+    for secret in secrets {
+        if values.contains_key(&secret.key) {
+            bail!(
+                "Multiple secrets have the key '{}', export a single project or use unique keys",
+                secret.key
+            );
+        }
+        values.insert(secret.key, secret.value);
+    }
+
+    let contents = match format {
+        FileFormat::Dotenv => dotenv::to_string(&values)?,
+        FileFormat::Json => {
+            let mut text = serde_json::to_string_pretty(&values)?;
+            text.push('\n');
+            text
+        }
+        FileFormat::Yaml => serde_yaml::to_string(&values)?,
+    };
+
+    match out_file {
+        Some(path) => {
+            write_private_file(&path, &contents)?;
+            match values.len() {
+                1 => println!("1 secret exported to {}.", path.display()),
+                n => println!("{} secrets exported to {}.", n, path.display()),
+            }
+        }
+        None => print!("{contents}"),
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn import(
+    client: Client,
+    organization_id: Uuid,
+    import: SecretImportCommandModel,
+    output_settings: OutputSettings,
+) -> Result<()> {
+    let entries = read_secrets_file(&import.file, import.format)?;
+    if let Some(key) = entries.iter().map(|(key, _)| key).duplicates().next() {
+        bail!(
+            "The key '{}' appears more than once in {}",
+            key,
+            import.file.display()
+        );
+    }
+
+    let mut existing: HashMap<String, SecretResponse> = HashMap::new();
+    let mut ambiguous_keys = HashSet::new();
+    for secret in fetch_secrets(&client, organization_id, Some(import.project_id)).await? {
+        if let Some(other) = existing.insert(secret.key.clone(), secret) {
+            ambiguous_keys.insert(other.key);
+        }
+    }
+
+    let mut plan = Vec::new();
+    let mut conflicts = Vec::new();
+    for (key, value) in entries {
+        if ambiguous_keys.contains(&key) {
+            bail!("Multiple secrets in the project have the key '{}'", key);
+        }
+
+        let action = match existing.get(&key) {
+            None => ImportAction::Create,
+            Some(secret) if secret.value == value => ImportAction::Unchanged,
+            Some(_) => match import.on_conflict {
+                ConflictPolicy::Skip => ImportAction::Skip,
+                ConflictPolicy::Overwrite => ImportAction::Update,
+                ConflictPolicy::Fail => {
+                    conflicts.push(key.clone());
+                    ImportAction::Skip
+                }
+            },
+        };
+        plan.push((key, value, action));
+    }
+
+    if !conflicts.is_empty() {
+        bail!(
+            "These keys already exist in the project with a different value: {}. Use \
+             --on-conflict skip or --on-conflict overwrite to import anyway.",
+            conflicts.join(", ")
+        );
+    }
+
+    let mut results = Vec::new();
+    let mut secrets_failed = Vec::new();
+    for (key, value, action) in plan {
+        let secret = existing.get(&key);
+        let response = match (action, secret) {
+            (ImportAction::Create, _) if import.dry_run => Ok(None),
+            (ImportAction::Create, _) => client
+                .secrets()
+                .create(&SecretCreateRequest {
+                    organization_id,
+                    key: key.clone(),
+                    value,
+                    note: String::new(),
+                    project_ids: Some(vec![import.project_id]),
+                })
+                .await
+                .map(|created| Some(created.id)),
+            (ImportAction::Update, Some(secret)) if !import.dry_run => client
+                .secrets()
+                .update(&SecretPutRequest {
+                    id: secret.id,
+                    organization_id,
+                    key: key.clone(),
+                    value,
+                    note: secret.note.clone(),
+                    project_ids: Some(vec![import.project_id]),
+                })
+                .await
+                .map(|updated| Some(updated.id)),
+            (_, secret) => Ok(secret.map(|s| s.id)),
+        };
+
+        match response {
+            Ok(id) => results.push(ImportResult { key, action, id }),
+            Err(e) => {
+                secrets_failed.push((key.clone(), e.to_string()));
+                results.push(ImportResult {
+                    key,
+                    action: ImportAction::Failed,
+                    id: secret.map(|s| s.id),
+                });
+            }
+        }
+    }
+
+    serialize_response(results, output_settings)?;
+    if import.dry_run {
+        eprintln!("Dry run, no secrets were changed.");
+    }
+
+    report_errors("import", &secrets_failed)
+}
+
+pub(crate) async fn diff(
+    client: Client,
+    organization_id: Uuid,
+    diff: SecretDiffCommandModel,
+    output_settings: OutputSettings,
+) -> Result<()> {
+    let from = fetch_diff_side(
+        &client,
+        organization_id,
+        &diff,
+        &diff.from,
+        diff.from_access_token.as_deref(),
+    )
+    .await?;
+    let to = fetch_diff_side(
+        &client,
+        organization_id,
+        &diff,
+        &diff.to,
+        diff.to_access_token.as_deref(),
+    )
+    .await?;
+
+    serialize_response(compare(&from, &to, diff.show_values), output_settings)?;
 
     Ok(())
 }
 
+/// Splits a `[PROFILE:]PROJECT_ID` side of a diff into its profile and project
+fn parse_diff_side(side: &str) -> Result<(Option<&str>, Uuid)> {
+    let (profile, project) = match side.rsplit_once(':') {
+        Some((profile, project)) if !profile.is_empty() => (Some(profile), project),
+        Some(_) => bail!("Missing profile name in '{}'", side),
+        None => (None, side),
+    };
+    let Ok(project_id) = Uuid::parse_str(project) else {
+        bail!(
+            "Invalid project ID '{}', expected [PROFILE:]PROJECT_ID",
+            project
+        );
+    };
+    Ok((profile, project_id))
+}
+
+/// Logs in again for a side with its own profile or access token. A profile brings its own server
+/// URL, otherwise the global `--server-url` and `--profile` still apply.
+fn diff_side_login(
+    diff: &SecretDiffCommandModel,
+    profile: Option<&str>,
+    access_token: Option<&str>,
+) -> Option<DiffSideLogin> {
+    if profile.is_none() && access_token.is_none() {
+        return None;
+    }
+
+    Some(DiffSideLogin {
+        access_token: access_token.unwrap_or(&diff.access_token).to_owned(),
+        server_url: match profile {
+            Some(_) => None,
+            None => diff.server_url.clone(),
+        },
+        profile: profile.map(str::to_owned).or_else(|| diff.profile.clone()),
+    })
+}
+
+/// Fetches the secrets of one side of a diff as a map of keys to values. Sides with a profile or
+/// access token of their own log in again, see [diff_side_login].
+async fn fetch_diff_side(
+    client: &Client,
+    organization_id: Uuid,
+    diff: &SecretDiffCommandModel,
+    side: &str,
+    access_token: Option<&str>,
+) -> Result<BTreeMap<String, String>> {
+    let (profile, project_id) = parse_diff_side(side)?;
+
+    let secrets = match diff_side_login(diff, profile, access_token) {
+        None => fetch_secrets(client, organization_id, Some(project_id)).await?,
+        Some(login) => {
+            let (client, organization_id) = crate::login(
+                login.access_token,
+                &login.server_url,
+                &login.profile,
+                &diff.config_file,
+            )
+            .await?;
+            let Some(organization_id) = organization_id else {
+                bail!(
+                    "The access token for '{}' isn't associated to an organization",
+                    side
+                );
+            };
+            fetch_secrets(&client, organization_id, Some(project_id)).await?
+        }
+    };
+
+    let mut values = BTreeMap::new();
+    for secret in secrets {
+        if values.contains_key(&secret.key) {
+            bail!(
+                "Multiple secrets in project {} have the key '{}'",
+                project_id,
+                secret.key
+            );
+        }
+        values.insert(secret.key, secret.value);
+    }
+    Ok(values)
+}
+
+/// Lists the keys that are only in `to` (added), only in `from` (removed) or have different
+/// values, sorted by key. Values are replaced by hashes unless `show_values` is set.
+fn compare(
+    from: &BTreeMap<String, String>,
+    to: &BTreeMap<String, String>,
+    show_values: bool,
+) -> Vec<SecretDiff> {
+    // A new key for every run, so the hashes can't be compared across runs or against guesses
+    let hash_key: [u8; 32] = rand::random();
+    let display = |value: &String| match show_values {
+        true => value.clone(),
+        false => hash_value(&hash_key, value),
+    };
+
+    from.keys()
+        .merge(to.keys())
+        .dedup()
+        .filter_map(|key| {
+            let status = match (from.get(key), to.get(key)) {
+                (Some(_), None) => DiffStatus::Removed,
+                (None, Some(_)) => DiffStatus::Added,
+                (Some(a), Some(b)) if a != b => DiffStatus::Changed,
+                _ => return None,
+            };
+            Some(SecretDiff {
+                key: key.clone(),
+                status,
+                from: from.get(key).map(display),
+                to: to.get(key).map(display),
+            })
+        })
+        .collect()
+}
+
+/// A short HMAC-SHA256 of a value, enough to tell values apart within a run without showing them
+fn hash_value(key: &[u8], value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC to accept keys of any size");
+    mac.update(value.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest[..6].iter().map(|b| format!("{b:02x}")).collect();
+    format!("hmac:{hex}")
+}
+
+/// The entries of a JSON or YAML object in file order, keeping repeated keys so that `import` can
+/// reject them instead of silently using the last one
+struct FileEntries<V>(Vec<(String, V)>);
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for FileEntries<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor<V>(PhantomData<V>);
+
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for EntriesVisitor<V> {
+            type Value = FileEntries<V>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of secret keys to values")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(FileEntries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor(PhantomData))
+    }
+}
+
+/// Reads the `KEY=value` pairs of a dotenv, JSON or YAML file. Without a format it's chosen by
+/// the file extension. JSON and YAML files hold a single object, whose values can be strings,
+/// numbers or booleans.
+fn read_secrets_file(path: &Path, format: Option<FileFormat>) -> Result<Vec<(String, String)>> {
+    let format = format.unwrap_or_else(|| match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => FileFormat::Json,
+        Some("yaml" | "yml") => FileFormat::Yaml,
+        _ => FileFormat::Dotenv,
+    });
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => bail!("Could not read {}: {}", path.display(), e),
+    };
+
+    match format {
+        FileFormat::Dotenv => dotenv::parse(&text),
+        FileFormat::Json => serde_json::from_str::<FileEntries<serde_json::Value>>(&text)?
+            .0
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(value) => value,
+                    serde_json::Value::Number(value) => value.to_string(),
+                    serde_json::Value::Bool(value) => value.to_string(),
+                    _ => bail!("The value of '{}' must be a string, number or boolean", key),
+                };
+                Ok((key, value))
+            })
+            .collect(),
+        FileFormat::Yaml => serde_yaml::from_str::<FileEntries<serde_yaml::Value>>(&text)?
+            .0
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_yaml::Value::String(value) => value,
+                    serde_yaml::Value::Number(value) => value.to_string(),
+                    serde_yaml::Value::Bool(value) => value.to_string(),
+                    _ => bail!("The value of '{}' must be a string, number or boolean", key),
+                };
+                Ok((key, value))
+            })
+            .collect(),
+    }
+}
+
 pub(crate) async fn get(
     client: Client,
     secret_id: Uuid,
@@ -132,7 +737,7 @@ pub(crate) async fn get(
         .secrets()
         .get(&SecretGetRequest { id: secret_id })
         .await?;
-    serialize_response(secret, output_settings);
+    serialize_response(secret, output_settings)?;
 
     Ok(())
 }
@@ -153,7 +758,7 @@ pub(crate) async fn create(
             project_ids: Some(vec![secret.project_id]),
         })
         .await?;
-    serialize_response(secret, output_settings);
+    serialize_response(secret, output_settings)?;
 
     Ok(())
 }
@@ -183,7 +788,7 @@ pub(crate) async fn edit(
                 .map(|id| vec![id]),
         })
         .await?;
-    serialize_response(new_secret, output_settings);
+    serialize_response(new_secret, output_settings)?;
 
     Ok(())
 }
@@ -201,27 +806,252 @@ pub(crate) async fn delete(client: Client, secret_ids: Vec<Uuid>) -> Result<()>
         .into_iter()
         .filter_map(|r| r.error.map(|e| (r.id, e)))
         .collect();
-    let deleted_secrets = count - secrets_failed.len();
 
-    match deleted_secrets {
-        2.. => println!("{} secrets deleted successfully.", deleted_secrets),
-        1 => println!("{} secret deleted successfully.", deleted_secrets),
+    report_results("delete", "deleted", count, &secrets_failed)
+}
+
+/// Moves or copies the secrets to `project_id`. They're fetched in a single request, and a secret
+/// that fails doesn't stop the others.
+pub(crate) async fn transfer(
+    client: Client,
+    organization_id: Uuid,
+    secret_ids: Vec<Uuid>,
+    project_id: Uuid,
+    transfer: Transfer,
+) -> Result<()> {
+    let secret_ids: Vec<Uuid> = secret_ids.into_iter().unique().collect();
+    let count = secret_ids.len();
+
+    let secrets = client
+        .secrets()
+        .get_by_ids(SecretsGetRequest {
+            ids: secret_ids.clone(),
+        })
+        .await?
+        .data;
+
+    let mut secrets_failed: Vec<(Uuid, String)> = secret_ids
+        .into_iter()
+        .filter(|id| !secrets.iter().any(|s| s.id == *id))
+        .map(|id| (id, "Secret not found".to_owned()))
+        .collect();
+
+    for secret in secrets {
+        let id = secret.id;
+        let project_ids = Some(vec![project_id]);
+        let result = match transfer {
+            Transfer::Move => {
+                client
+                    .secrets()
+                    .update(&SecretPutRequest {
+                        id,
+                        organization_id,
+                        key: secret.key,
+                        value: secret.value,
+                        note: secret.note,
+                        project_ids,
+                    })
+                    .await
+            }
+            Transfer::Copy => {
+                client
+                    .secrets()
+                    .create(&SecretCreateRequest {
+                        organization_id,
+                        key: secret.key,
+                        value: secret.value,
+                        note: secret.note,
+                        project_ids,
+                    })
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            secrets_failed.push((id, e.to_string()));
+        }
+    }
+
+    match transfer {
+        Transfer::Move => report_results("move", "moved", count, &secrets_failed),
+        Transfer::Copy => report_results("copy", "copied", count, &secrets_failed),
+    }
+}
+
+/// Prints how many of `count` secrets succeeded and the errors of the ones that didn't, and
+/// fails if any did
+pub(crate) fn report_results(
+    verb: &str,
+    past_tense: &str,
+    count: usize,
+    secrets_failed: &[(impl Display, String)],
+) -> Result<()> {
+    let succeeded = count - secrets_failed.len();
+
+    match succeeded {
+        2.. => println!("{} secrets {} successfully.", succeeded, past_tense),
+        1 => println!("{} secret {} successfully.", succeeded, past_tense),
         _ => (),
     }
 
+    report_errors(verb, secrets_failed)
+}
+
+/// Prints the errors of the secrets that failed, keyed by their ID or key, and fails if there
+/// are any
+pub(crate) fn report_errors(verb: &str, secrets_failed: &[(impl Display, String)]) -> Result<()> {
     match secrets_failed.len() {
         2.. => eprintln!("{} secrets had errors:", secrets_failed.len()),
         1 => eprintln!("{} secret had an error:", secrets_failed.len()),
         _ => (),
     }
 
-    for secret in &secrets_failed {
+    for secret in secrets_failed {
         eprintln!("{}: {}", secret.0, secret.1);
     }
 
     if !secrets_failed.is_empty() {
-        bail!("Errors when attempting to delete secrets.");
+        bail!("Errors when attempting to {} secrets.", verb);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_diff_sides() {
+        let id = Uuid::parse_str("0b5ad0a4-6c31-4bd1-9d08-e0c3f6d41c3e").unwrap();
+
+        assert_eq!(parse_diff_side(&id.to_string()).unwrap(), (None, id));
+        assert_eq!(
+            parse_diff_side(&format!("prod:{id}")).unwrap(),
+            (Some("prod"), id)
+        );
+        assert!(parse_diff_side(&format!(":{id}")).is_err());
+        assert!(parse_diff_side("prod:not-a-uuid").is_err());
+    }
+
+    fn diff_model(server_url: Option<&str>, profile: Option<&str>) -> SecretDiffCommandModel {
+        SecretDiffCommandModel {
+            from: String::new(),
+            to: String::new(),
+            show_values: false,
+            from_access_token: None,
+            to_access_token: None,
+            access_token: "global-token".to_string(),
+            server_url: server_url.map(str::to_owned),
+            profile: profile.map(str::to_owned),
+            config_file: None,
+        }
+    }
+
+    #[test]
+    fn logs_in_again_only_for_other_profiles_or_tokens() {
+        let diff = diff_model(Some("https://vault.example.com"), Some("default"));
+
+        assert_eq!(diff_side_login(&diff, None, None), None);
+        assert_eq!(
+            diff_side_login(&diff, None, Some("side-token")),
+            Some(DiffSideLogin {
+                access_token: "side-token".to_string(),
+                server_url: Some("https://vault.example.com".to_string()),
+                profile: Some("default".to_string()),
+            })
+        );
+        assert_eq!(
+            diff_side_login(&diff, Some("prod"), None),
+            Some(DiffSideLogin {
+                access_token: "global-token".to_string(),
+                server_url: None,
+                profile: Some("prod".to_string()),
+            })
+        );
+        assert_eq!(
+            diff_side_login(&diff, Some("prod"), Some("side-token")),
+            Some(DiffSideLogin {
+                access_token: "side-token".to_string(),
+                server_url: None,
+                profile: Some("prod".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn compares_by_key() {
+        let from = map(&[("A", "1"), ("B", "2"), ("C", "3")]);
+        let to = map(&[("B", "2"), ("C", "4"), ("D", "5")]);
+
+        let diffs = compare(&from, &to, true);
+        let summary: Vec<_> = diffs
+            .iter()
+            .map(|d| (d.key.as_str(), d.status, d.from.as_deref(), d.to.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("A", DiffStatus::Removed, Some("1"), None),
+                ("C", DiffStatus::Changed, Some("3"), Some("4")),
+                ("D", DiffStatus::Added, None, Some("5")),
+            ]
+        );
+    }
+
+    #[test]
+    fn hides_values_by_default() {
+        let diffs = compare(&map(&[("A", "secret")]), &map(&[("A", "other")]), false);
+
+        let from = diffs[0].from.as_deref().unwrap();
+        assert!(from.starts_with("hmac:"));
+        assert!(!from.contains("secret"));
+        assert_ne!(diffs[0].from, diffs[0].to);
+        assert_eq!(hash_value(b"key", "secret"), hash_value(b"key", "secret"));
+        assert_ne!(hash_value(b"key", "secret"), hash_value(b"other", "secret"));
+    }
+
+    fn read_file(name: &str, contents: &str) -> Result<Vec<(String, String)>> {
+        let directory = tempfile::tempdir().expect("temporary directory");
+        let path = directory.path().join(name);
+        std::fs::write(&path, contents).expect("writable file");
+        read_secrets_file(&path, None)
+    }
+
+    #[test]
+    fn reads_json_in_file_order_with_repeated_keys() {
+        let entries = read_file("secrets.json", r#"{"B": "1", "A": 2, "B": true}"#).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ("B".to_string(), "1".to_string()),
+                ("A".to_string(), "2".to_string()),
+                ("B".to_string(), "true".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_yaml_in_file_order_with_repeated_keys() {
+        let entries = read_file("secrets.yaml", "B: 1\nA: two\nB: 3\n").unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ("B".to_string(), "1".to_string()),
+                ("A".to_string(), "two".to_string()),
+                ("B".to_string(), "3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_nested_values() {
+        assert!(read_file("secrets.json", r#"{"A": {"B": "1"}}"#).is_err());
+    }
+}
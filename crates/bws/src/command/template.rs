@@ -0,0 +1,320 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use bitwarden::{
+    secrets_manager::{
+        secrets::{
+            SecretIdentifiersByProjectRequest, SecretIdentifiersRequest, SecretResponse,
+            SecretsGetRequest,
+        },
+        ClientSecretsExt,
+    },
+    Client,
+};
+use color_eyre::eyre::{bail, Result};
+use itertools::Itertools;
+use regex::{Captures, Regex};
+use uuid::Uuid;
+
+use crate::{util::write_private_file, TemplateCommand};
+
+const PLACEHOLDER_REGEX: &str = r#"\{\{\s*(bws|bws_id)\s+"([^"]*)"\s*\}\}"#;
+const PLACEHOLDER_START_REGEX: &str = r"\{\{\s*bws(_id)?(?-u:\b)";
+
+/// A secret referenced by a placeholder
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Reference {
+    Key(String),
+    Id(Uuid),
+}
+
+pub(crate) async fn process_command(
+    command: TemplateCommand,
+    client: Client,
+    organization_id: Uuid,
+) -> Result<()> {
+    match command {
+        TemplateCommand::Render {
+            input,
+            out_file,
+            project,
+        } => render(client, organization_id, input, out_file, project).await,
+    }
+}
+
+pub(crate) async fn render(
+    client: Client,
+    organization_id: Uuid,
+    input: PathBuf,
+    out_file: Option<PathBuf>,
+    project_id: Option<Uuid>,
+) -> Result<()> {
+    let template = match std::fs::read_to_string(&input) {
+        Ok(template) => template,
+        Err(e) => bail!("Could not read {}: {}", input.display(), e),
+    };
+
+    let placeholder = Regex::new(PLACEHOLDER_REGEX).expect("PLACEHOLDER_REGEX to be a valid regex");
+    let references = parse_references(&template, &placeholder)?;
+
+    let values = resolve(&client, organization_id, project_id, &references).await?;
+
+    let unresolved: Vec<String> = references
+        .iter()
+        .filter(|(reference, _)| !values.contains_key(reference))
+        .map(|(reference, line)| match reference {
+            Reference::Key(key) => format!("  bws \"{}\" on line {}", key, line),
+            Reference::Id(id) => format!("  bws_id \"{}\" on line {}", id, line),
+        })
+        .unique()
+        .collect();
+    if !unresolved.is_empty() {
+        bail!(
+            "No secrets found for these references:\n{}",
+            unresolved.join("\n")
+        );
+    }
+
+    let rendered = placeholder.replace_all(&template, |captures: &Captures| {
+        to_reference(captures)
+            .ok()
+            .and_then(|reference| values.get(&reference))
+            .cloned()
+            .unwrap_or_default()
+    });
+
+    match out_file {
+        Some(path) => {
+            write_private_file(&path, &rendered)?;
+            println!("{} rendered to {}.", input.display(), path.display());
+        }
+        None => print!("{rendered}"),
+    }
+
+    Ok(())
+}
+
+/// The references in `template`, with the line they're on. Anything that starts like a
+/// placeholder but isn't one is an error, rather than being left in the output.
+fn parse_references(template: &str, placeholder: &Regex) -> Result<Vec<(Reference, usize)>> {
+    let line_of = |index: usize| template[..index].matches('\n').count() + 1;
+
+    let mut references = Vec::new();
+    let mut placeholder_starts = HashSet::new();
+    for captures in placeholder.captures_iter(template) {
+        let Some(whole) = captures.get(0) else {
+            continue;
+        };
+        placeholder_starts.insert(whole.start());
+
+        match to_reference(&captures) {
+            Ok(reference) => references.push((reference, line_of(whole.start()))),
+            Err(value) => bail!(
+                "Invalid secret ID \"{}\" on line {}",
+                value,
+                line_of(whole.start())
+            ),
+        }
+    }
+
+    let start =
+        Regex::new(PLACEHOLDER_START_REGEX).expect("PLACEHOLDER_START_REGEX to be a valid regex");
+    if let Some(malformed) = start
+        .find_iter(template)
+        .find(|m| !placeholder_starts.contains(&m.start()))
+    {
+        bail!(
+            "Invalid placeholder on line {}, expected {{{{ bws \"KEY\" }}}} or {{{{ bws_id \"<uuid>\" }}}}",
+            line_of(malformed.start())
+        );
+    }
+
+    Ok(references)
+}
+
+/// The reference of a placeholder match, or the text that isn't a valid secret ID
+fn to_reference(captures: &Captures) -> Result<Reference, String> {
+    let value = captures.get(2).map(|m| m.as_str()).unwrap_or_default();
+    match captures.get(1).map(|m| m.as_str()) {
+        Some("bws_id") => Uuid::parse_str(value)
+            .map(Reference::Id)
+            .map_err(|_| value.to_string()),
+        _ => Ok(Reference::Key(value.to_string())),
+    }
+}
+
+/// Looks up the values of `references`, fetching all the secrets with a single `get_by_ids` call.
+/// References that can't be found are left out, keys used by multiple secrets are an error.
+async fn resolve(
+    client: &Client,
+    organization_id: Uuid,
+    project_id: Option<Uuid>,
+    references: &[(Reference, usize)],
+) -> Result<HashMap<Reference, String>> {
+    let keys: HashSet<&str> = references
+        .iter()
+        .filter_map(|(reference, _)| match reference {
+            Reference::Key(key) => Some(key.as_str()),
+            Reference::Id(_) => None,
+        })
+        .collect();
+
+    let mut ids_by_key: HashMap<String, Vec<Uuid>> = HashMap::new();
+    if !keys.is_empty() {
+        let identifiers = match project_id {
+            Some(project_id) => {
+                client
+                    .secrets()
+                    .list_by_project(&SecretIdentifiersByProjectRequest { project_id })
+                    .await?
+            }
+            None => {
+                client
+                    .secrets()
+                    .list(&SecretIdentifiersRequest { organization_id })
+                    .await?
+            }
+        };
+        for identifier in identifiers.data {
+            if keys.contains(identifier.key.as_str()) {
+                ids_by_key
+                    .entry(identifier.key)
+                    .or_default()
+                    .push(identifier.id);
+            }
+        }
+    }
+
+    if let Some((key, _)) = ids_by_key.iter().find(|(_, ids)| ids.len() > 1) {
+        bail!(
+            "Multiple secrets have the key \"{}\", use {{{{ bws_id \"<uuid>\" }}}} or --project",
+            key
+        );
+    }
+
+    let ids: Vec<Uuid> = references
+        .iter()
+        .filter_map(|(reference, _)| match reference {
+            Reference::Id(id) => Some(*id),
+            Reference::Key(_) => None,
+        })
+        .chain(ids_by_key.values().flatten().copied())
+        .unique()
+        .collect();
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let secrets = client
+        .secrets()
+        .get_by_ids(SecretsGetRequest { ids })
+        .await?
+        .data;
+
+    Ok(values_by_reference(secrets, &ids_by_key))
+}
+
+/// Maps every secret by its ID, and by its key only if it's the secret the key was looked up as,
+/// so a `bws_id` secret that happens to share a referenced key doesn't stand in for it
+fn values_by_reference(
+    secrets: Vec<SecretResponse>,
+    ids_by_key: &HashMap<String, Vec<Uuid>>,
+) -> HashMap<Reference, String> {
+    let mut values = HashMap::new();
+    for secret in secrets {
+        if ids_by_key
+            .get(&secret.key)
+            .is_some_and(|ids| ids.contains(&secret.id))
+        {
+            values.insert(Reference::Key(secret.key.clone()), secret.value.clone());
+        }
+        values.insert(Reference::Id(secret.id), secret.value);
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn references(template: &str) -> Result<Vec<(Reference, usize)>> {
+        let placeholder = Regex::new(PLACEHOLDER_REGEX).expect("valid regex");
+        parse_references(template, &placeholder)
+    }
+
+    #[test]
+    fn test_parse_references() {
+        let template = "user: {{ bws \"DB_USER\" }}\npassword: {{bws_id \"759130d0-29dd-48bd-831a-e3bdbafeeb6e\"}}\n";
+        assert_eq!(
+            references(template).expect("valid template"),
+            vec![
+                (Reference::Key("DB_USER".to_string()), 1),
+                (
+                    Reference::Id(
+                        Uuid::parse_str("759130d0-29dd-48bd-831a-e3bdbafeeb6e")
+                            .expect("valid uuid")
+                    ),
+                    2
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_references_ignores_other_braces() {
+        assert!(references("{{ .Values.name }} and {{ other \"KEY\" }}")
+            .expect("valid template")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parse_references_errors() {
+        assert!(references("{{ bws_id \"not-a-uuid\" }}").is_err());
+        assert!(references("{{ bws KEY }}").is_err());
+        assert!(references("{{ bws \"KEY\" ").is_err());
+        assert!(references("{{ bws_id }}").is_err());
+    }
+
+    #[test]
+    fn test_parse_references_ignores_longer_names() {
+        assert!(references("{{ bwsfoo }} and {{ bws_identity \"KEY\" }}")
+            .expect("valid template")
+            .is_empty());
+    }
+
+    fn secret(key: &str, value: &str) -> SecretResponse {
+        SecretResponse {
+            id: Uuid::new_v4(),
+            organization_id: Uuid::new_v4(),
+            project_id: None,
+            key: key.to_string(),
+            value: value.to_string(),
+            note: String::new(),
+            creation_date: Utc::now(),
+            revision_date: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_values_by_reference_maps_keys_to_the_looked_up_secret() {
+        let by_key = secret("DB_PASSWORD", "from project");
+        let by_id = secret("DB_PASSWORD", "from elsewhere");
+        let ids_by_key = HashMap::from([("DB_PASSWORD".to_string(), vec![by_key.id])]);
+        let (key_id, other_id) = (by_key.id, by_id.id);
+
+        // The `bws_id` secret comes last so it would overwrite the key's value if it were mapped
+        let values = values_by_reference(vec![by_key, by_id], &ids_by_key);
+
+        assert_eq!(
+            values[&Reference::Key("DB_PASSWORD".to_string())],
+            "from project"
+        );
+        assert_eq!(values[&Reference::Id(key_id)], "from project");
+        assert_eq!(values[&Reference::Id(other_id)], "from elsewhere");
+    }
+}
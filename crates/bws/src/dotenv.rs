@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::{bail, Result};
+
+use crate::util::is_valid_posix_name;
+
+/// Quotes a value for a dotenv file. Values are always double quoted, with backslashes, quotes,
+/// `$` and backticks escaped so nothing is interpolated, and line breaks written as `\n` and `\r`.
+pub(crate) fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '$' => quoted.push_str("\\$"),
+            '`' => quoted.push_str("\\`"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Formats `secrets` as `KEY="value"` lines. Keys that aren't POSIX-compliant names are an error,
+/// as shells and most dotenv parsers can't read them back.
+pub(crate) fn to_string(secrets: &BTreeMap<String, String>) -> Result<String> {
+    if let Some(key) = secrets.keys().find(|key| !is_valid_posix_name(key)) {
+        bail!(
+            "The key '{}' is not a POSIX-compliant name and can't be written to a dotenv file",
+            key
+        );
+    }
+
+    Ok(secrets
+        .iter()
+        .map(|(key, value)| format!("{}={}\n", key, quote(value)))
+        .collect())
+}
+
+/// Parses a dotenv file into its `KEY=value` pairs, in order. Lines can start with `export`, and
+/// blank lines and `#` comments are ignored. Values are unquoted, single quoted and taken
+/// literally, or double quoted with the escapes written by [quote] and possibly spanning lines.
+pub(crate) fn parse(text: &str) -> Result<Vec<(String, String)>> {
+    let mut secrets = Vec::new();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let number = index + 1;
+        let line = line.trim_start();
+        if line.trim_end().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line
+            .strip_prefix("export ")
+            .map(str::trim_start)
+            .unwrap_or(line);
+        let Some((key, rest)) = line.split_once('=') else {
+            bail!("Line {}: expected KEY=value", number);
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+            bail!("Line {}: '{}' is not a valid key", number, key);
+        }
+
+        let rest = rest.trim_start();
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut raw = quoted.to_string();
+            let end = loop {
+                if let Some(end) = closing_quote(&raw) {
+                    break end;
+                }
+                let Some((_, next)) = lines.next() else {
+                    bail!("Line {}: the double quoted value is never closed", number);
+                };
+                raw.push('\n');
+                raw.push_str(next);
+            };
+            check_trailing(&raw[end + 1..], number)?;
+            unescape(&raw[..end])
+        } else if let Some(quoted) = rest.strip_prefix('\'') {
+            let Some(end) = quoted.find('\'') else {
+                bail!("Line {}: the single quoted value is never closed", number);
+            };
+            check_trailing(&quoted[end + 1..], number)?;
+            quoted[..end].to_string()
+        } else {
+            let value = match rest.find(" #") {
+                Some(comment) => &rest[..comment],
+                None => rest,
+            };
+            value.trim_end().to_string()
+        };
+
+        secrets.push((key.to_string(), value));
+    }
+
+    Ok(secrets)
+}
+
+/// The index of the first quote in `raw` that isn't escaped
+fn closing_quote(raw: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in raw.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unescape(raw: &str) -> String {
+    let mut value = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('t') => value.push('\t'),
+            Some(c @ ('\\' | '"' | '$' | '`')) => value.push(c),
+            Some(c) => {
+                value.push('\\');
+                value.push(c);
+            }
+            None => value.push('\\'),
+        }
+    }
+    value
+}
+
+/// Only whitespace and a comment may follow a quoted value
+fn check_trailing(trailing: &str, number: usize) -> Result<()> {
+    let trailing = trailing.trim();
+    if !trailing.is_empty() && !trailing.starts_with('#') {
+        bail!(
+            "Line {}: unexpected '{}' after the quoted value",
+            number,
+            trailing
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("plain"), r#""plain""#);
+        assert_eq!(quote(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote("$HOME and `id`"), r#""\$HOME and \`id\`""#);
+        assert_eq!(quote("line1\nline2\r\n"), r#""line1\nline2\r\n""#);
+        assert_eq!(quote(r"C:\path"), r#""C:\\path""#);
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = r#"
+# a comment
+PLAIN=value
+export EXPORTED = spaced value # with a comment
+SINGLE='literal $HOME "quotes" \n'
+DOUBLE="escaped \"quotes\" \$HOME\nnext line" # comment
+MULTI="first
+second"
+EMPTY=
+"#;
+        assert_eq!(
+            parse(text).expect("valid dotenv"),
+            vec![
+                ("PLAIN".to_string(), "value".to_string()),
+                ("EXPORTED".to_string(), "spaced value".to_string()),
+                (
+                    "SINGLE".to_string(),
+                    r#"literal $HOME "quotes" \n"#.to_string()
+                ),
+                (
+                    "DOUBLE".to_string(),
+                    "escaped \"quotes\" $HOME\nnext line".to_string()
+                ),
+                ("MULTI".to_string(), "first\nsecond".to_string()),
+                ("EMPTY".to_string(), "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("NO_EQUALS").is_err());
+        assert!(parse("BAD KEY=value").is_err());
+        assert!(parse("OPEN=\"never closed").is_err());
+        assert!(parse("OPEN='never closed").is_err());
+        assert!(parse("TRAILING=\"value\" extra").is_err());
+    }
+
+    #[test]
+    fn test_parse_reads_quoted_values() {
+        let values = [
+            "plain",
+            "with \"quotes\" and 'single' ones",
+            "$HOME `id` \\ backslash",
+            "multiple\nlines\r\n",
+        ];
+        for value in values {
+            let line = format!("KEY={}", quote(value));
+            assert_eq!(
+                parse(&line).expect("quoted value"),
+                vec![("KEY".to_string(), value.to_string())]
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_string() {
+        let secrets = BTreeMap::from([
+            ("B".to_string(), "two".to_string()),
+            ("A".to_string(), "o\"ne".to_string()),
+        ]);
+        assert_eq!(
+            to_string(&secrets).expect("valid keys"),
+            "A=\"o\\\"ne\"\nB=\"two\"\n"
+        );
+
+        let secrets = BTreeMap::from([("not valid".to_string(), "value".to_string())]);
+        assert!(to_string(&secrets).is_err());
+    }
+}
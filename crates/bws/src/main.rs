@@ -1,4 +1,4 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 
 use bitwarden::{
     auth::{login::AccessTokenLoginRequest, AccessToken},
@@ -10,10 +10,13 @@ use color_eyre::eyre::{bail, Result};
 use config::Profile;
 use log::error;
 use render::OutputSettings;
+use uuid::Uuid;
 
 mod cli;
 mod command;
 mod config;
+mod dotenv;
+mod plan;
 mod render;
 mod state;
 mod util;
@@ -66,14 +69,205 @@ async fn process_commands() -> Result<()> {
         Some(key) => key,
         None => bail!("Missing access token"),
     };
-    let access_token_obj: AccessToken = access_token.parse()?;
 
-    let profile = get_config_profile(
+    let (client, organization_id) = login(
+        access_token.clone(),
         &cli.server_url,
         &cli.profile,
         &cli.config_file,
-        &access_token,
-    )?;
+    )
+    .await?;
+    let Some(organization_id) = organization_id else {
+        error!("Access token isn't associated to an organization.");
+        return Ok(());
+    };
+
+    let output = match cli.raw {
+        true => Output::Value,
+        false => cli.output,
+    };
+    let output_settings = OutputSettings::new(output, color);
+
+    // And finally we process all the commands which require authentication
+    match command {
+        Commands::Project {
+            cmd:
+                ProjectCommand::Clone {
+                    project_id,
+                    name,
+                    include_keys,
+                    exclude_keys,
+                    dry_run,
+                    to_profile,
+                    to_access_token,
+                },
+        } => {
+            // Only log in again when the copy goes somewhere else
+            let target = match (to_profile, to_access_token) {
+                (None, None) => None,
+                (to_profile, to_access_token) => {
+                    let server_url = match to_profile {
+                        Some(_) => None,
+                        None => cli.server_url.clone(),
+                    };
+                    let (client, organization_id) = login(
+                        to_access_token.unwrap_or(access_token),
+                        &server_url,
+                        &to_profile.or(cli.profile),
+                        &cli.config_file,
+                    )
+                    .await?;
+                    let Some(organization_id) = organization_id else {
+                        bail!("The access token for the copy isn't associated to an organization");
+                    };
+                    Some((client, organization_id))
+                }
+            };
+
+            command::project::clone(
+                client,
+                organization_id,
+                target,
+                command::project::ProjectCloneCommandModel {
+                    project_id,
+                    name,
+                    include_keys,
+                    exclude_keys,
+                    dry_run,
+                },
+                output_settings,
+            )
+            .await
+        }
+
+        Commands::Project { cmd } => {
+            command::project::process_command(cmd, client, organization_id, output_settings).await
+        }
+
+        Commands::Secret {
+            cmd:
+                SecretCommand::Diff {
+                    from,
+                    to,
+                    show_values,
+                    from_access_token,
+                    to_access_token,
+                },
+        } => {
+            command::secret::diff(
+                client,
+                organization_id,
+                command::secret::SecretDiffCommandModel {
+                    from,
+                    to,
+                    show_values,
+                    from_access_token,
+                    to_access_token,
+                    access_token,
+                    server_url: cli.server_url,
+                    profile: cli.profile,
+                    config_file: cli.config_file,
+                },
+                output_settings,
+            )
+            .await
+        }
+
+        Commands::Secret { cmd } => {
+            command::secret::process_command(cmd, client, organization_id, output_settings).await
+        }
+
+        Commands::Apply {
+            manifest,
+            apply,
+            prune,
+        } => {
+            command::apply::apply(
+                client,
+                organization_id,
+                command::apply::ApplyCommandModel {
+                    manifest,
+                    apply,
+                    prune,
+                },
+                output_settings,
+            )
+            .await
+        }
+
+        Commands::Template { cmd } => {
+            command::template::process_command(cmd, client, organization_id).await
+        }
+
+        Commands::Run {
+            command,
+            shell,
+            no_shell,
+            no_inherit_env,
+            mask,
+            watch,
+            reload_signal,
+            restart_signal,
+            grace_period,
+            project_ids,
+            secret_ids,
+            keys,
+            exclude_keys,
+            uuids_as_keynames,
+            sanitize_keys,
+            env_prefix,
+            map,
+        } => {
+            let exit_code = command::run::run(
+                client,
+                organization_id,
+                command::run::SecretSelection {
+                    project_ids,
+                    secret_ids,
+                    keys,
+                    exclude_keys,
+                },
+                command::run::EnvNaming {
+                    uuids_as_keynames,
+                    sanitize_keys,
+                    env_prefix,
+                    map,
+                },
+                command::run::ProcessOptions {
+                    no_inherit_env,
+                    shell,
+                    no_shell,
+                    mask,
+                    watch: watch.map(Duration::from_secs),
+                    reload_signal,
+                    restart_signal,
+                    grace_period: Duration::from_secs(grace_period),
+                },
+                command,
+            )
+            .await?;
+
+            // exit with the exit code from the child process
+            std::process::exit(exit_code);
+        }
+
+        Commands::Config { .. } | Commands::Completions { .. } => {
+            unreachable!()
+        }
+    }
+}
+
+/// Logs in with `access_token`, using the server settings from `server_url` or the profile.
+/// Returns the client along with the organization of the access token, if it has one.
+pub(crate) async fn login(
+    access_token: String,
+    server_url: &Option<String>,
+    profile: &Option<String>,
+    config_file: &Option<PathBuf>,
+) -> Result<(bitwarden::Client, Option<Uuid>)> {
+    let access_token_obj: AccessToken = access_token.parse()?;
+
+    let profile = get_config_profile(server_url, profile, config_file, &access_token)?;
 
     let settings = profile
         .clone()
@@ -111,52 +305,8 @@ async fn process_commands() -> Result<()> {
         })
         .await?;
 
-    let organization_id = match client.internal.get_access_token_organization() {
-        Some(id) => id,
-        None => {
-            error!("Access token isn't associated to an organization.");
-            return Ok(());
-        }
-    };
-
-    let output_settings = OutputSettings::new(cli.output, color);
-
-    // And finally we process all the commands which require authentication
-    match command {
-        Commands::Project { cmd } => {
-            command::project::process_command(cmd, client, organization_id, output_settings).await
-        }
-
-        Commands::Secret { cmd } => {
-            command::secret::process_command(cmd, client, organization_id, output_settings).await
-        }
-
-        Commands::Run {
-            command,
-            shell,
-            no_inherit_env,
-            project_id,
-            uuids_as_keynames,
-        } => {
-            let exit_code = command::run::run(
-                client,
-                organization_id,
-                project_id,
-                uuids_as_keynames,
-                no_inherit_env,
-                shell,
-                command,
-            )
-            .await?;
-
-            // exit with the exit code from the child process
-            std::process::exit(exit_code);
-        }
-
-        Commands::Config { .. } | Commands::Completions { .. } => {
-            unreachable!()
-        }
-    }
+    let organization_id = client.internal.get_access_token_organization();
+    Ok((client, organization_id))
 }
 
 fn get_config_profile(
@@ -0,0 +1,19 @@
+use serde::Serialize;
+
+/// What `apply` or `project clone --dry-run` would do with a project or secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ChangeAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A row of the plan. Changes to the project itself have no key.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PlannedChange {
+    pub(crate) action: ChangeAction,
+    pub(crate) project: String,
+    pub(crate) key: Option<String>,
+}
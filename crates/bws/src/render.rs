@@ -1,10 +1,17 @@
 use bitwarden::secrets_manager::{projects::ProjectResponse, secrets::SecretResponse};
 use bitwarden_cli::Color;
 use chrono::{DateTime, Utc};
+use color_eyre::eyre::{bail, Result};
 use comfy_table::Table;
 use serde::Serialize;
 
-use crate::{cli::Output, util::is_valid_posix_name};
+use crate::{
+    cli::Output,
+    command::secret::{DiffStatus, ImportAction, ImportResult, SecretDiff},
+    dotenv::quote,
+    plan::{ChangeAction, PlannedChange},
+    util::is_valid_posix_name,
+};
 
 const ASCII_HEADER_ONLY: &str = "     --            ";
 
@@ -22,7 +29,7 @@ impl OutputSettings {
 pub(crate) fn serialize_response<T: Serialize + TableSerialize<N>, const N: usize>(
     data: T,
     output_settings: OutputSettings,
-) {
+) -> Result<()> {
     match output_settings.output {
         Output::JSON => {
             let mut text =
@@ -43,10 +50,10 @@ pub(crate) fn serialize_response<T: Serialize + TableSerialize<N>, const N: usiz
                 .into_iter()
                 .map(|row| {
                     if is_valid_posix_name(&row[1]) {
-                        format!("{}=\"{}\"", row[1], row[2])
+                        format!("{}={}", row[1], quote(&row[2]))
                     } else {
                         commented_out = true;
-                        format!("# {}=\"{}\"", row[1], row[2].replace('\n', "\n# "))
+                        format!("# {}={}", row[1], quote(&row[2]))
                     }
                 })
                 .collect();
@@ -82,8 +89,20 @@ pub(crate) fn serialize_response<T: Serialize + TableSerialize<N>, const N: usiz
                 .collect();
             println!("{}", rows.join("\n"));
         }
+        Output::Value => match data.get_raw_values().as_deref() {
+            // No trailing newline, so the output can be used as is
+            Some([value]) => print!("{value}"),
+            Some([]) => bail!("There is no secret to print the value of"),
+            Some(values) => bail!(
+                "The value output only supports a single secret, but {} were returned",
+                values.len()
+            ),
+            None => bail!("The value output is only supported for secrets"),
+        },
         Output::None => {}
     }
+
+    Ok(())
 }
 
 fn pretty_print(language: &str, data: &str, color: Color) {
@@ -103,6 +122,11 @@ fn pretty_print(language: &str, data: &str, color: Color) {
 pub(crate) trait TableSerialize<const N: usize>: Sized {
     fn get_headers() -> [&'static str; N];
     fn get_values(&self) -> Vec<[String; N]>;
+
+    /// The values printed by [Output::Value], one per row, or `None` for types without a value
+    fn get_raw_values(&self) -> Option<Vec<String>> {
+        None
+    }
 }
 
 // Generic impl for Vec<T> so we can call `serialize_response` with both individual
@@ -118,6 +142,13 @@ impl<T: TableSerialize<N>, const N: usize> TableSerialize<N> for Vec<T> {
         }
         values
     }
+    fn get_raw_values(&self) -> Option<Vec<String>> {
+        let mut values = Vec::new();
+        for t in self {
+            values.append(&mut t.get_raw_values()?);
+        }
+        Some(values)
+    }
 }
 
 fn format_date(date: &DateTime<Utc>) -> String {
@@ -151,4 +182,71 @@ impl TableSerialize<4> for SecretResponse {
             format_date(&self.creation_date),
         ]]
     }
+
+    fn get_raw_values(&self) -> Option<Vec<String>> {
+        Some(vec![self.value.clone()])
+    }
+}
+
+impl TableSerialize<3> for ImportResult {
+    fn get_headers() -> [&'static str; 3] {
+        ["Key", "Action", "ID"]
+    }
+
+    fn get_values(&self) -> Vec<[String; 3]> {
+        let action = match self.action {
+            ImportAction::Create => "create",
+            ImportAction::Update => "update",
+            ImportAction::Skip => "skip",
+            ImportAction::Unchanged => "unchanged",
+            ImportAction::Failed => "failed",
+        };
+
+        vec![[
+            self.key.clone(),
+            action.to_string(),
+            self.id.map(|id| id.to_string()).unwrap_or_default(),
+        ]]
+    }
+}
+
+impl TableSerialize<3> for PlannedChange {
+    fn get_headers() -> [&'static str; 3] {
+        ["Action", "Project", "Key"]
+    }
+
+    fn get_values(&self) -> Vec<[String; 3]> {
+        let action = match self.action {
+            ChangeAction::Create => "create",
+            ChangeAction::Update => "update",
+            ChangeAction::Delete => "delete",
+        };
+
+        vec![[
+            action.to_string(),
+            self.project.clone(),
+            self.key.clone().unwrap_or_default(),
+        ]]
+    }
+}
+
+impl TableSerialize<4> for SecretDiff {
+    fn get_headers() -> [&'static str; 4] {
+        ["Key", "Status", "From", "To"]
+    }
+
+    fn get_values(&self) -> Vec<[String; 4]> {
+        let status = match self.status {
+            DiffStatus::Added => "added",
+            DiffStatus::Removed => "removed",
+            DiffStatus::Changed => "changed",
+        };
+
+        vec![[
+            self.key.clone(),
+            status.to_string(),
+            self.from.clone().unwrap_or_default(),
+            self.to.clone().unwrap_or_default(),
+        ]]
+    }
 }
@@ -1,3 +1,6 @@
+use std::{io::Write, path::Path};
+
+use color_eyre::eyre::Result;
 use regex::Regex;
 use uuid::Uuid;
 
@@ -18,6 +21,36 @@ pub(crate) fn string_to_bool(value: &str) -> Result<bool, &str> {
     }
 }
 
+/// Converts a glob pattern, where `*` matches any characters and `?` a single character, to a
+/// regex that matches the whole input.
+pub(crate) fn glob_to_regex(pattern: &str) -> Regex {
+    let pattern = regex::escape(pattern)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    Regex::new(&format!("^{pattern}$")).expect("escaped glob to be a valid regex")
+}
+
+/// Converts a secret key to a POSIX-compliant environment variable name, e.g. `db-password` to
+/// `DB_PASSWORD`.
+///
+/// Characters that aren't allowed are replaced with underscores, letters are uppercased, and a
+/// leading digit is prefixed with an underscore.
+pub(crate) fn to_posix_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+
+    match name.chars().next() {
+        Some(c) if c.is_ascii_digit() => format!("_{name}"),
+        Some(_) => name,
+        None => "_".to_string(),
+    }
+}
+
 /// Converts a UUID to a POSIX-compliant environment variable name.
 ///
 /// POSIX environment variable names must start with a letter or an underscore
@@ -26,6 +59,24 @@ pub(crate) fn uuid_to_posix(uuid: &Uuid) -> String {
     format!("_{}", uuid.to_string().replace('-', "_"))
 }
 
+/// Replaces the file at `path` with `contents`, through a temporary file in the same directory
+/// so readers never see a partially written file. On Unix the file is only accessible by the
+/// current user.
+pub(crate) fn write_private_file(path: &Path, contents: &str) -> Result<()> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    // Temporary files are created with 0600 permissions, which the renamed file keeps
+    let mut file = tempfile::NamedTempFile::new_in(directory)?;
+    file.write_all(contents.as_bytes())?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+
+    Ok(())
+}
+
 mod tests {
     #[allow(unused_imports)]
     use super::*;
@@ -61,6 +112,48 @@ mod tests {
         assert!(is_valid_posix_name(&uuid_to_posix(&uuid::Uuid::new_v4())));
     }
 
+    #[test]
+    fn test_to_posix_name() {
+        assert_eq!(to_posix_name("db-password"), "DB_PASSWORD");
+        assert_eq!(to_posix_name("DB_PASSWORD"), "DB_PASSWORD");
+        assert_eq!(to_posix_name("apiKey"), "APIKEY");
+        assert_eq!(to_posix_name("1st secret"), "_1ST_SECRET");
+        assert_eq!(to_posix_name("héllo"), "H_LLO");
+        assert_eq!(to_posix_name(""), "_");
+        assert!(is_valid_posix_name(&to_posix_name("a.b-c d/e")));
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        assert!(glob_to_regex("DB_*").is_match("DB_PASSWORD"));
+        assert!(glob_to_regex("DB_*").is_match("DB_"));
+        assert!(!glob_to_regex("DB_*").is_match("MY_DB_PASSWORD"));
+        assert!(glob_to_regex("*_KEY").is_match("API_KEY"));
+        assert!(glob_to_regex("KEY_?").is_match("KEY_1"));
+        assert!(!glob_to_regex("KEY_?").is_match("KEY_10"));
+        assert!(glob_to_regex("a.b").is_match("a.b"));
+        assert!(!glob_to_regex("a.b").is_match("axb"));
+        assert!(glob_to_regex("exact").is_match("exact"));
+    }
+
+    #[test]
+    fn test_write_private_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.env");
+
+        write_private_file(&path, "first").unwrap();
+        write_private_file(&path, "second").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
     #[test]
     fn test_string_to_bool_true_true() {
         let result = string_to_bool("true");
@@ -23,28 +23,111 @@ run_test() {
   fi
 }
 
+PROJECT_ID="2c8e3e8a-7d3b-4a8f-9b61-1f0b6f4e3a01"
+EMPTY_PROJECT_ID="2c8e3e8a-7d3b-4a8f-9b61-1f0b6f4e3a02"
+FERRIS_ID="5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a01"
+TUX_ID="5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a02"
+BTW_ID="5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a03"
+
 secrets() {
   run_test "secret list"   "bws secret list | grep -q 'FERRIS'"
-  run_test "secret get"    "bws secret get $(uuidgen) | grep -q 'btw'"
-  run_test "secret create" "bws secret create 'secret-key' 'secret-value' --note 'optional note' $(uuidgen) | grep -q 'secret-key'"
-  run_test "secret edit"   "bws secret edit --key 'something-new' --value 'new-value' --note 'updated note' $(uuidgen) | grep -q 'something-new'"
-  run_test "secret delete" "bws secret delete $(uuidgen) $(uuidgen) $(uuidgen) | grep -q '3 secrets deleted successfully.'"
+  run_test "secret get"    "bws secret get $BTW_ID | grep -q 'btw'"
+  run_test "secret get by key" "bws secret get --key btw --project $PROJECT_ID | grep -q 'I use Arch, btw'"
+  run_test "secret get unknown key" "! bws secret get --key no-such-key 2>/dev/null"
+  run_test "secret get raw value" "[ \"\$(bws secret get --raw $BTW_ID)\" = 'I use Arch, btw' ]"
+  run_test "secret export" "bws secret export --project $PROJECT_ID | grep -q '^btw=\"I use Arch, btw\"$'"
+  run_test "secret export file" "bws secret export --format json --project $PROJECT_ID -O /tmp/secrets-export.json >/dev/null && ls -l /tmp/secrets-export.json | grep -q '^-rw-------' && grep -q '\"btw\": \"I use Arch, btw\"' /tmp/secrets-export.json"
+  run_test "secret list raw fails" "! bws secret list --output value 2>/dev/null"
+  run_test "secret edit by key" "bws secret edit --by-key btw --note 'edited by key' | grep -q 'edited by key'"
+  run_test "secret create" "bws secret create 'secret-key' 'secret-value' --note 'optional note' $PROJECT_ID | grep -q 'secret-key'"
+  run_test "secret edit"   "bws secret edit --key 'something-new' --value 'new-value' --note 'updated note' $TUX_ID | grep -q 'something-new'"
+  run_test "secret get after edit" "bws secret get $TUX_ID | grep -q 'updated note'"
+  run_test "secret copy"   "bws secret copy $FERRIS_ID --to $EMPTY_PROJECT_ID | grep -q '1 secret copied successfully.'"
+  run_test "secret move"   "bws secret move $TUX_ID --to $EMPTY_PROJECT_ID | grep -q '1 secret moved successfully.'"
+  run_test "secret get after move" "bws secret get $TUX_ID | grep -q '$EMPTY_PROJECT_ID'"
+  run_test "secret move partial success" "bws secret move $BTW_ID $(uuidgen) --to $PROJECT_ID 2>/dev/null | grep -q '1 secret moved successfully.'"
+  run_test "secret move partial failure" "! bws secret move $BTW_ID $(uuidgen) --to $PROJECT_ID >/dev/null 2>&1"
+  run_test "secret delete" "bws secret delete $FERRIS_ID $TUX_ID $BTW_ID | grep -q '3 secrets deleted successfully.'"
+  run_test "secret delete unknown" "! bws secret delete $(uuidgen) 2>/dev/null"
+  printf 'IMPORTED="first value"\nOTHER=plain\n' >/tmp/secrets-import.env
+  run_test "secret import dry run" "bws secret import /tmp/secrets-import.env --project $EMPTY_PROJECT_ID --dry-run -o tsv | grep -q 'IMPORTED	create'"
+  run_test "secret import" "bws secret import /tmp/secrets-import.env --project $EMPTY_PROJECT_ID -o tsv | grep -q 'OTHER	create'"
+  run_test "secret import unchanged" "bws secret import /tmp/secrets-import.env --project $EMPTY_PROJECT_ID -o tsv | grep -q 'IMPORTED	unchanged'"
+  printf '{"IMPORTED": "second value"}' >/tmp/secrets-import.json
+  run_test "secret import conflict" "! bws secret import /tmp/secrets-import.json --project $EMPTY_PROJECT_ID 2>/dev/null"
+  run_test "secret import overwrite" "bws secret import /tmp/secrets-import.json --project $EMPTY_PROJECT_ID --on-conflict overwrite -o tsv | grep -q 'IMPORTED	update'"
+  run_test "secret diff" "bws secret diff --from $PROJECT_ID --to $EMPTY_PROJECT_ID -o tsv | grep -q 'OTHER	added		hmac:'"
+  run_test "secret diff hides values" "! bws secret diff --from $PROJECT_ID --to $EMPTY_PROJECT_ID | grep -q 'plain'"
+  run_test "secret diff show values" "bws secret diff --from $PROJECT_ID --to $EMPTY_PROJECT_ID --show-values -o tsv | grep -q 'OTHER	added		plain'"
+  run_test "secret diff uses the side's access token" "! bws secret diff --from $PROJECT_ID --to $EMPTY_PROJECT_ID --to-access-token 'not-a-token'"
+}
+
+run_command() {
+  run_test "run with key"         "bws run --key btw -- 'echo \$btw' | grep -q 'I use Arch, btw'"
+  run_test "run with project"     "bws run --project-id $PROJECT_ID --exclude-key 'F*' -- 'env' | grep -q '^TUX='"
+  run_test "run excludes keys"    "! bws run --project-id $PROJECT_ID --exclude-key 'F*' -- 'env' | grep -q '^FERRIS='"
+  run_test "run with secret id"   "bws run --secret-id $BTW_ID -- 'echo \$btw' | grep -q 'I use Arch, btw'"
+  run_test "run with unknown key" "! bws run --key no-such-key -- true 2>/dev/null"
+  run_test "run with env prefix"  "bws run --key btw --env-prefix APP_ -- 'echo \$APP_btw' | grep -q 'I use Arch, btw'"
+  run_test "run with map"         "bws run --key btw --map btw=BTW -- 'echo \$BTW' | grep -q 'I use Arch, btw'"
+  run_test "run with sanitize"    "bws run --key btw --sanitize-keys -- 'echo \$BTW' | grep -q 'I use Arch, btw'"
+  run_test "run without shell"    "bws run --key btw --no-shell -- printf '%s|%s' 'two words' '\$btw' | grep -q '^two words|\$btw$'"
+  run_test "run with mask"        "! bws run --key btw --mask -- 'echo \$btw; echo \$btw >&2' 2>&1 | grep -q 'Arch'"
+  run_test "run with mask output" "[ \"\$(bws run --key btw --mask -- 'echo value: \$btw')\" = 'value: ***' ]"
+  run_test "run killed by signal"  "bws run -- 'kill -TERM \$\$'; [ \$? -eq 143 ]"
+}
+
+templates() {
+  printf 'value: {{ bws "btw" }}\nby id: {{ bws_id "%s" }}\n' "$BTW_ID" >/tmp/template.txt
+  printf 'value: {{ bws "no-such-key" }}\n' >/tmp/template-unresolved.txt
+  run_test "template render"            "bws template render /tmp/template.txt | grep -q '^by id: I use Arch, btw$'"
+  run_test "template render file"       "bws template render /tmp/template.txt -O /tmp/template.out >/dev/null && ls -l /tmp/template.out | grep -q '^-rw-------'"
+  run_test "template render unresolved" "! bws template render /tmp/template-unresolved.txt 2>/dev/null"
 }
 
 projects() {
   run_test "project list"   "bws project list | grep -q 'Production Environment'"
-  run_test "project get"    "bws project get $(uuidgen) | grep -q 'Production Environment'"
+  run_test "project get"    "bws project get $PROJECT_ID | grep -q 'Production Environment'"
   run_test "project create" "bws project create 'project-name' | grep -q 'project-name'"
-  run_test "project edit"   "bws project edit --name 'new-project-name' $(uuidgen) | grep -q 'new-project-name'"
-  run_test "project delete" "bws project delete $(uuidgen) $(uuidgen) | grep -q '2 projects deleted successfully.'"
+  run_test "project edit"   "bws project edit --name 'new-project-name' $EMPTY_PROJECT_ID | grep -q 'new-project-name'"
+  run_test "project clone dry run" "bws project clone $PROJECT_ID --name 'cloned-project' --include-key 'btw' --dry-run -o tsv | grep -q 'create	cloned-project	btw'"
+  run_test "project clone"  "bws project clone $PROJECT_ID --name 'cloned-project' --include-key 'btw' | grep -q '1 secret copied successfully.'"
+  run_test "project delete" "bws project delete $PROJECT_ID $EMPTY_PROJECT_ID | grep -q '2 projects deleted successfully.'"
+}
+
+manifests() {
+  cat >/tmp/manifest.yaml <<EOF
+projects:
+  - name: Managed Project
+    secrets:
+      - key: MANAGED
+        value: from the manifest
+      - key: FROM_ENV
+        env: HOME
+EOF
+  run_test "apply plan"      "bws apply -m /tmp/manifest.yaml -o tsv 2>/dev/null | grep -q '^create	Managed Project	MANAGED$'"
+  run_test "apply"           "bws apply -m /tmp/manifest.yaml --apply -o none 2>&1 | grep -q 'Applied 3 changes.'"
+  run_test "apply unchanged" "bws apply -m /tmp/manifest.yaml 2>&1 | grep -q 'No changes'"
 }
 
 main() {
   pushd "${REPO_ROOT}" >/dev/null || exit 1
+  echo "Testing run..."
+  run_command
+  echo
+
+  echo "Testing templates..."
+  templates
+  echo
+
   echo "Testing secrets..."
   secrets
   echo
 
+  echo "Testing manifests..."
+  manifests
+  echo
+
   echo "Testing projects..."
   projects
 
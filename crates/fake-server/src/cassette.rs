@@ -0,0 +1,491 @@
+// Recording of request/response pairs to a JSONL cassette, and replaying them instead of the real
+// route handlers
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bitwarden_crypto::SymmetricCryptoKey;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::{keys::decrypt, routes::ApiError, state::AppState};
+
+/// One line of a cassette
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    /// JSON bodies are stored as JSON, anything else (like the form encoded login) as a string
+    pub body: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// Stored like [RecordedRequest::body]
+    pub body: Option<Value>,
+}
+
+/// Appends every interaction to a cassette file
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<File>>,
+}
+
+impl Recorder {
+    /// Creates the cassette, truncating any previous recording
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("Could not create cassette: {}", path.display()))?;
+
+        Ok(Recorder {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    async fn record(&self, interaction: &Interaction) -> Result<()> {
+        let mut line = serde_json::to_string(interaction)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().await;
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Serves the responses of a cassette. Requests are matched by method, path, query and JSON body,
+/// in the order they were recorded, and each interaction is served once.
+#[derive(Debug, Clone)]
+pub struct Player {
+    remaining: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl Player {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read cassette: {}", path.display()))?;
+
+        let interactions = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Invalid interaction on line {}", index + 1))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Player {
+            remaining: Arc::new(Mutex::new(interactions)),
+        })
+    }
+
+    /// Interactions that haven't been served yet
+    pub async fn remaining(&self) -> Vec<Interaction> {
+        self.remaining.lock().await.clone()
+    }
+
+    /// Takes the first unused interaction recorded for `request`, decrypting ciphers in the bodies
+    /// with `keys`
+    async fn take(
+        &self,
+        request: &RecordedRequest,
+        keys: &[SymmetricCryptoKey],
+    ) -> Option<Interaction> {
+        let mut remaining = self.remaining.lock().await;
+        let index = remaining
+            .iter()
+            .position(|i| matches(&i.request, request, keys))?;
+
+        Some(remaining.remove(index))
+    }
+}
+
+/// Whether `request` is the one that was recorded. Ciphers are compared by their plaintext because
+/// clients encrypt with a random IV, and bodies that aren't JSON, like the form encoded login,
+/// aren't compared.
+fn matches(
+    recorded: &RecordedRequest,
+    request: &RecordedRequest,
+    keys: &[SymmetricCryptoKey],
+) -> bool {
+    if recorded.method != request.method
+        || recorded.path != request.path
+        || recorded.query != request.query
+    {
+        return false;
+    }
+
+    match (&recorded.body, &request.body) {
+        (Some(Value::String(_)), Some(Value::String(_))) => true,
+        (Some(recorded), Some(body)) => plaintext(recorded, keys) == plaintext(body, keys),
+        (recorded, body) => recorded == body,
+    }
+}
+
+/// `value` with every string that is a cipher of one of `keys` replaced by its plaintext
+fn plaintext(value: &Value, keys: &[SymmetricCryptoKey]) -> Value {
+    match value {
+        Value::String(text) => Value::String(
+            keys.iter()
+                .find_map(|key| decrypt(key, text).ok())
+                .unwrap_or_else(|| text.clone()),
+        ),
+        Value::Array(values) => Value::Array(values.iter().map(|v| plaintext(v, keys)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(name, v)| (name.clone(), plaintext(v, keys)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// The keys of the organizations in the fixtures
+fn organization_keys(state: &AppState) -> Vec<SymmetricCryptoKey> {
+    state
+        .fixtures()
+        .organizations
+        .iter()
+        .filter_map(|o| o.key.clone())
+        .filter_map(|key| SymmetricCryptoKey::try_from(key).ok())
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub enum Cassette {
+    Record(Recorder),
+    Replay(Player),
+}
+
+/// Middleware that records or replays every request, depending on the configured [Cassette].
+/// `/admin` routes are passed through untouched.
+pub async fn middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(cassette) = state.cassette() else {
+        return next.run(request).await;
+    };
+    if request.uri().path().starts_with("/admin") {
+        return next.run(request).await;
+    }
+
+    let (recorded_request, request) = match capture(request).await {
+        Ok(captured) => captured,
+        Err(response) => return response,
+    };
+
+    match cassette {
+        Cassette::Record(recorder) => {
+            let response = next.run(request).await;
+
+            let (parts, body) = response.into_parts();
+            let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
+
+            let interaction = Interaction {
+                request: recorded_request,
+                response: RecordedResponse {
+                    status: parts.status.as_u16(),
+                    content_type: content_type(&parts.headers),
+                    body: to_value(&bytes, &parts.headers),
+                },
+            };
+            if let Err(e) = recorder.record(&interaction).await {
+                error!("Could not record interaction: {:?}", e);
+            }
+
+            Response::from_parts(parts, Body::from(bytes))
+        }
+        Cassette::Replay(player) => {
+            let method = recorded_request.method.as_str();
+            let path = recorded_request.path.as_str();
+
+            match player
+                .take(&recorded_request, &organization_keys(&state))
+                .await
+            {
+                Some(interaction) => {
+                    info!("Replaying {} {}", method, path);
+                    replay(interaction.response)
+                }
+                None => {
+                    error!("Unexpected request in replay mode: {:?}", recorded_request);
+                    ApiError {
+                        status: StatusCode::NOT_IMPLEMENTED,
+                        message: format!("Unexpected request in replay mode: {method} {path}"),
+                    }
+                    .into_response()
+                }
+            }
+        }
+    }
+}
+
+/// Buffers the body of `request`, returning its recorded form and an equivalent request that can
+/// still be handled
+pub(crate) async fn capture(request: Request) -> Result<(RecordedRequest, Request), Response> {
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return Err(StatusCode::BAD_REQUEST.into_response());
+    };
+
+    let recorded = RecordedRequest {
+        method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(str::to_string),
+        body: to_value(&bytes, &parts.headers),
+    };
+
+    Ok((recorded, Request::from_parts(parts, Body::from(bytes))))
+}
+
+fn content_type(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn to_value(bytes: &Bytes, headers: &HeaderMap) -> Option<Value> {
+    if bytes.is_empty() {
+        return None;
+    }
+
+    let is_json = content_type(headers).is_some_and(|c| c.contains("json"));
+    match is_json {
+        true => serde_json::from_slice(bytes).ok(),
+        false => None,
+    }
+    .or_else(|| Some(Value::String(String::from_utf8_lossy(bytes).into_owned())))
+}
+
+fn replay(recorded: RecordedResponse) -> Response {
+    let status = StatusCode::from_u16(recorded.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    // None of the API responses are bare JSON strings, so strings are bodies that weren't JSON
+    let body = match recorded.body {
+        None => Body::empty(),
+        Some(Value::String(text)) => Body::from(text),
+        Some(value) => Body::from(value.to_string()),
+    };
+
+    let mut response = (status, body).into_response();
+    if let Some(value) = recorded
+        .content_type
+        .and_then(|c| HeaderValue::from_str(&c).ok())
+    {
+        response.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::keys::{encrypt, random_organization_key};
+
+    fn request(method: &str, path: &str) -> RecordedRequest {
+        RecordedRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: None,
+            body: None,
+        }
+    }
+
+    fn interaction(method: &str, path: &str, status: u16) -> Interaction {
+        Interaction {
+            request: request(method, path),
+            response: RecordedResponse {
+                status,
+                content_type: None,
+                body: None,
+            },
+        }
+    }
+
+    fn cassette(interactions: &[Interaction]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for interaction in interactions {
+            writeln!(file, "{}", serde_json::to_string(interaction).unwrap()).unwrap();
+        }
+        file
+    }
+
+    #[tokio::test]
+    async fn take_serves_interactions_in_recorded_order() {
+        let file = cassette(&[
+            interaction("GET", "/api/secrets/1", 200),
+            interaction("POST", "/api/secrets/delete", 200),
+            interaction("GET", "/api/secrets/1", 404),
+        ]);
+        let player = Player::load(file.path()).unwrap();
+
+        let first = player
+            .take(&request("GET", "/api/secrets/1"), &[])
+            .await
+            .unwrap();
+        assert_eq!(first.response.status, 200);
+        let second = player
+            .take(&request("GET", "/api/secrets/1"), &[])
+            .await
+            .unwrap();
+        assert_eq!(second.response.status, 404);
+
+        let remaining = player.remaining().await;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].request.path, "/api/secrets/delete");
+    }
+
+    #[tokio::test]
+    async fn take_matches_method_and_path_until_exhausted() {
+        let file = cassette(&[interaction("GET", "/api/secrets/1", 200)]);
+        let player = Player::load(file.path()).unwrap();
+
+        assert!(player
+            .take(&request("PUT", "/api/secrets/1"), &[])
+            .await
+            .is_none());
+        assert!(player
+            .take(&request("GET", "/api/secrets/2"), &[])
+            .await
+            .is_none());
+        assert!(player
+            .take(&request("GET", "/api/secrets/1"), &[])
+            .await
+            .is_some());
+        assert!(player
+            .take(&request("GET", "/api/secrets/1"), &[])
+            .await
+            .is_none());
+        assert!(player.remaining().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn take_compares_queries_and_json_bodies() {
+        let keys = [SymmetricCryptoKey::try_from(random_organization_key()).unwrap()];
+        let key = &keys[0];
+        let body = |key_name: &str| {
+            json!({
+                "key": encrypt(key, key_name).unwrap(),
+                "projectIds": ["5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a03"],
+            })
+        };
+        let create = |body: Value, query: Option<&str>| RecordedRequest {
+            query: query.map(str::to_string),
+            body: Some(body),
+            ..request("POST", "/api/organizations/1/secrets")
+        };
+
+        let mut recorded = interaction("POST", "/api/organizations/1/secrets", 200);
+        recorded.request = create(body("FERRIS"), None);
+        let file = cassette(&[recorded]);
+        let player = Player::load(file.path()).unwrap();
+
+        assert!(player
+            .take(&create(body("OTHER"), None), &keys)
+            .await
+            .is_none());
+        assert!(player
+            .take(&create(body("FERRIS"), Some("force=true")), &keys)
+            .await
+            .is_none());
+        assert!(player
+            .take(&create(json!({"key": "FERRIS"}), None), &keys)
+            .await
+            .is_none());
+        // Encrypted again, as a client would
+        assert!(player
+            .take(&create(body("FERRIS"), None), &keys)
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn take_ignores_form_bodies() {
+        let form = |body: &str| RecordedRequest {
+            body: Some(Value::String(body.to_string())),
+            ..request("POST", "/identity/connect/token")
+        };
+
+        let mut recorded = interaction("POST", "/identity/connect/token", 200);
+        recorded.request = form("grant_type=client_credentials&client_secret=recorded");
+        let file = cassette(&[recorded]);
+        let player = Player::load(file.path()).unwrap();
+
+        assert!(player
+            .take(
+                &form("grant_type=client_credentials&client_secret=other"),
+                &[]
+            )
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn load_reads_recordings() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let recorder = Recorder::create(file.path()).unwrap();
+        recorder
+            .record(&interaction("GET", "/api/secrets/1", 200))
+            .await
+            .unwrap();
+        recorder
+            .record(&interaction("POST", "/api/secrets/delete", 200))
+            .await
+            .unwrap();
+
+        let player = Player::load(file.path()).unwrap();
+        let paths: Vec<_> = player
+            .remaining()
+            .await
+            .into_iter()
+            .map(|i| i.request.path)
+            .collect();
+        assert_eq!(paths, ["/api/secrets/1", "/api/secrets/delete"]);
+    }
+
+    #[test]
+    fn load_skips_blank_lines_and_reports_invalid_ones() {
+        let mut file = cassette(&[interaction("GET", "/api/secrets/1", 200)]);
+        writeln!(file).unwrap();
+        writeln!(file, "not json").unwrap();
+
+        let error = Player::load(file.path()).unwrap_err();
+        assert!(error.to_string().contains("line 3"), "{error}");
+    }
+}
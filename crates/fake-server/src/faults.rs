@@ -0,0 +1,250 @@
+// Simulated failures that are injected in front of the regular route handlers
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::{routes::ApiError, state::AppState};
+
+/// A failure to inject into every request matching `method` and `path`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fault {
+    /// HTTP method to match, any method when omitted
+    pub method: Option<String>,
+    /// Path to match. A `*` segment matches any single segment and a trailing `**` matches the
+    /// rest of the path, e.g. `/api/secrets/*` or `/api/**`
+    pub path: String,
+    /// Number of matching requests to fail, after which the fault is removed. Applies to every
+    /// matching request when omitted.
+    pub count: Option<u32>,
+    #[serde(flatten)]
+    pub kind: FaultKind,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum FaultKind {
+    /// `401 Unauthorized`, as returned for an expired or revoked bearer token
+    Unauthorized,
+    /// `429 Too Many Requests` with a `Retry-After` header
+    RateLimited { retry_after: u64 },
+    /// A server error, `500 Internal Server Error` unless `status` is set
+    ServerError { status: Option<u16> },
+    /// Waits before handling the request normally
+    Delay { millis: u64 },
+    /// `200 OK` with a body that isn't valid JSON
+    MalformedJson,
+}
+
+impl Fault {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        if let Some(m) = &self.method {
+            if !m.eq_ignore_ascii_case(method.as_str()) {
+                return false;
+            }
+        }
+
+        let mut actual = path.trim_matches('/').split('/');
+        for expected in self.path.trim_matches('/').split('/') {
+            match (expected, actual.next()) {
+                ("**", _) => return true,
+                (_, None) => return false,
+                ("*", Some(_)) => (),
+                (expected, Some(actual)) if expected == actual => (),
+                _ => return false,
+            }
+        }
+
+        actual.next().is_none()
+    }
+}
+
+/// The currently configured faults, shared between the middleware and the admin routes
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    active: Arc<Mutex<Vec<Fault>>>,
+}
+
+impl Faults {
+    pub fn new(faults: Vec<Fault>) -> Self {
+        Faults {
+            active: Arc::new(Mutex::new(faults)),
+        }
+    }
+
+    pub async fn list(&self) -> Vec<Fault> {
+        self.active.lock().await.clone()
+    }
+
+    pub async fn add(&self, faults: Vec<Fault>) {
+        self.active.lock().await.extend(faults);
+    }
+
+    pub async fn clear(&self) {
+        self.active.lock().await.clear();
+    }
+
+    /// Returns the first fault matching the request, consuming one of its remaining uses. Faults
+    /// with a `count` of zero never match.
+    async fn take(&self, method: &Method, path: &str) -> Option<FaultKind> {
+        let mut active = self.active.lock().await;
+        let index = active
+            .iter()
+            .position(|f| f.count != Some(0) && f.matches(method, path))?;
+
+        let fault = &mut active[index];
+        let kind = fault.kind.clone();
+        if let Some(count) = fault.count.as_mut() {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                active.remove(index);
+            }
+        }
+
+        Some(kind)
+    }
+}
+
+/// Middleware that replaces (or delays) the response of requests matching a configured fault
+pub async fn inject(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    if path.starts_with("/admin") {
+        return next.run(request).await;
+    }
+
+    let Some(kind) = state.faults().take(request.method(), &path).await else {
+        return next.run(request).await;
+    };
+    info!(
+        "Injecting fault into {} {}: {:?}",
+        request.method(),
+        path,
+        kind
+    );
+
+    match kind {
+        FaultKind::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
+        FaultKind::RateLimited { retry_after } => {
+            let mut response = ApiError {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                message: "Slow down! Too many requests. Try again later.".to_string(),
+            }
+            .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.into());
+            response
+        }
+        FaultKind::ServerError { status } => ApiError {
+            status: status
+                .and_then(|s| StatusCode::from_u16(s).ok())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            message: "An unhandled server error has occurred.".to_string(),
+        }
+        .into_response(),
+        FaultKind::Delay { millis } => {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            next.run(request).await
+        }
+        FaultKind::MalformedJson => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/json; charset=utf-8")],
+            r#"{"data": [{"id": "#,
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fault(method: Option<&str>, path: &str, count: Option<u32>) -> Fault {
+        Fault {
+            method: method.map(str::to_string),
+            path: path.to_string(),
+            count,
+            kind: FaultKind::Unauthorized,
+        }
+    }
+
+    #[test]
+    fn matches_paths() {
+        let exact = fault(None, "/api/secrets/delete", None);
+        assert!(exact.matches(&Method::POST, "/api/secrets/delete"));
+        assert!(exact.matches(&Method::POST, "api/secrets/delete/"));
+        assert!(!exact.matches(&Method::POST, "/api/secrets"));
+        assert!(!exact.matches(&Method::POST, "/api/secrets/delete/more"));
+
+        let segment = fault(None, "/api/secrets/*", None);
+        assert!(segment.matches(&Method::GET, "/api/secrets/5b7f9a4c"));
+        assert!(!segment.matches(&Method::GET, "/api/secrets"));
+        assert!(!segment.matches(&Method::GET, "/api/secrets/5b7f9a4c/more"));
+
+        let rest = fault(None, "/api/**", None);
+        assert!(rest.matches(&Method::GET, "/api/projects/5b7f9a4c/secrets"));
+        assert!(rest.matches(&Method::GET, "/api"));
+        assert!(!rest.matches(&Method::GET, "/identity/connect/token"));
+    }
+
+    #[test]
+    fn matches_methods() {
+        let any = fault(None, "/api/**", None);
+        assert!(any.matches(&Method::GET, "/api/secrets"));
+        assert!(any.matches(&Method::DELETE, "/api/secrets"));
+
+        let post = fault(Some("post"), "/api/**", None);
+        assert!(post.matches(&Method::POST, "/api/secrets"));
+        assert!(!post.matches(&Method::GET, "/api/secrets"));
+    }
+
+    #[tokio::test]
+    async fn take_consumes_counted_faults() {
+        let faults = Faults::new(vec![fault(None, "/api/**", Some(2))]);
+
+        assert!(faults.take(&Method::GET, "/api/secrets").await.is_some());
+        assert_eq!(faults.list().await[0].count, Some(1));
+        assert!(faults.take(&Method::GET, "/api/secrets").await.is_some());
+        assert!(faults.take(&Method::GET, "/api/secrets").await.is_none());
+        assert!(faults.list().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn take_keeps_unlimited_faults() {
+        let faults = Faults::new(vec![fault(None, "/api/**", None)]);
+
+        for _ in 0..3 {
+            assert!(faults.take(&Method::GET, "/api/secrets").await.is_some());
+        }
+        assert!(faults
+            .take(&Method::GET, "/identity/connect/token")
+            .await
+            .is_none());
+        assert_eq!(faults.list().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn take_skips_faults_with_zero_count() {
+        let faults = Faults::new(vec![
+            fault(None, "/api/**", Some(0)),
+            fault(Some("GET"), "/api/secrets", Some(1)),
+        ]);
+
+        assert!(faults.take(&Method::POST, "/api/secrets").await.is_none());
+        assert!(faults.take(&Method::GET, "/api/secrets").await.is_some());
+        assert!(faults.take(&Method::GET, "/api/secrets").await.is_none());
+    }
+}
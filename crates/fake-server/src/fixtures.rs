@@ -0,0 +1,190 @@
+// Plaintext description of the organizations, projects, secrets and machine accounts the server
+// starts with. Everything is encrypted with the organization key when the store is created.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use uuid::{uuid, Uuid};
+
+use crate::{
+    faults::Fault,
+    keys::{random_access_token, random_organization_key, Keys},
+};
+
+/// Organization ID of the default fixtures
+pub const ORGANIZATION_ID: Uuid = uuid!("f4e44a7f-1190-432a-9d4a-af96013127cb");
+
+/// Stable IDs for the default fixtures, so they can be referenced from scripts and tests
+pub const PROJECT_PRODUCTION_ID: Uuid = uuid!("2c8e3e8a-7d3b-4a8f-9b61-1f0b6f4e3a01");
+pub const PROJECT_STAGING_ID: Uuid = uuid!("2c8e3e8a-7d3b-4a8f-9b61-1f0b6f4e3a02");
+pub const SECRET_FERRIS_ID: Uuid = uuid!("5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a01");
+pub const SECRET_TUX_ID: Uuid = uuid!("5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a02");
+pub const SECRET_BTW_ID: Uuid = uuid!("5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a03");
+pub const MACHINE_ACCOUNT_ID: Uuid = uuid!("d34804ca-4f6c-4392-86b7-af96013175d0");
+
+const FERRIS: &str = r"
+    _~^~^~_
+\) /  o o  \ (/
+  '_   -   _'
+  / '-----' \
+";
+
+const TUX: &str = r"
+    .--.
+   |o_o |
+   |:_/ |
+  //   \ \
+ (|     | )
+/'\_   _/`\
+\___)=(___/
+";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Fixtures {
+    #[serde(default)]
+    pub organizations: Vec<OrganizationFixture>,
+    /// Faults that are active from startup, see [Fault]
+    #[serde(default)]
+    pub faults: Vec<Fault>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct OrganizationFixture {
+    pub id: Uuid,
+    /// Base64 encoded organization key, generated when missing
+    pub key: Option<String>,
+    #[serde(default)]
+    pub projects: Vec<ProjectFixture>,
+    #[serde(default)]
+    pub secrets: Vec<SecretFixture>,
+    #[serde(default)]
+    pub machine_accounts: Vec<MachineAccountFixture>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ProjectFixture {
+    pub id: Option<Uuid>,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SecretFixture {
+    pub id: Option<Uuid>,
+    pub key: String,
+    pub value: String,
+    #[serde(default)]
+    pub note: String,
+    pub project_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MachineAccountFixture {
+    pub id: Option<Uuid>,
+    pub name: String,
+    /// Access tokens in the `0.<id>.<client secret>:<encryption key>` format, one is generated
+    /// when empty
+    #[serde(default)]
+    pub access_tokens: Vec<String>,
+    /// Projects the account has access to. When omitted the account can read and write
+    /// everything in its organization, including secrets that aren't in a project.
+    pub projects: Option<Vec<ProjectAccessFixture>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ProjectAccessFixture {
+    pub id: Uuid,
+    #[serde(default)]
+    pub permission: Permission,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Permission {
+    #[default]
+    Read,
+    ReadWrite,
+}
+
+impl Fixtures {
+    /// Loads fixtures from a JSON file, or a YAML file for any other extension
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read fixtures: {}", path.display()))?;
+
+        let fixtures = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            _ => serde_yaml::from_str(&content)?,
+        };
+
+        Ok(fixtures)
+    }
+
+    /// The fixtures used when no file is provided, with the default organization using `keys`
+    pub fn seeded(keys: Keys) -> Self {
+        let project = |id, name: &str| ProjectFixture {
+            id: Some(id),
+            name: name.to_string(),
+        };
+
+        let secret = |id, key: &str, value: &str, note: &str| SecretFixture {
+            id: Some(id),
+            key: key.to_string(),
+            value: value.to_string(),
+            note: note.to_string(),
+            project_id: Some(PROJECT_PRODUCTION_ID),
+        };
+
+        Fixtures {
+            organizations: vec![OrganizationFixture {
+                id: ORGANIZATION_ID,
+                key: Some(keys.organization_key.to_base64().to_string()),
+                projects: vec![
+                    project(PROJECT_PRODUCTION_ID, "Production Environment"),
+                    project(PROJECT_STAGING_ID, "Staging Environment"),
+                ],
+                secrets: vec![
+                    secret(SECRET_FERRIS_ID, "FERRIS", FERRIS, "the crab"),
+                    secret(SECRET_TUX_ID, "TUX", TUX, "the penguin"),
+                    secret(SECRET_BTW_ID, "btw", "I use Arch, btw", ""),
+                ],
+                machine_accounts: vec![MachineAccountFixture {
+                    id: Some(MACHINE_ACCOUNT_ID),
+                    name: "fake-server".to_string(),
+                    access_tokens: vec![keys.access_token],
+                    projects: None,
+                }],
+            }],
+            faults: Vec::new(),
+        }
+    }
+
+    /// Fills in every generated value (IDs, keys and access tokens), so building a store from the
+    /// result always produces the same data
+    pub fn resolved(mut self) -> Self {
+        for org in self.organizations.iter_mut() {
+            org.key.get_or_insert_with(random_organization_key);
+
+            for project in org.projects.iter_mut() {
+                project.id.get_or_insert_with(Uuid::new_v4);
+            }
+            for secret in org.secrets.iter_mut() {
+                secret.id.get_or_insert_with(Uuid::new_v4);
+            }
+            for account in org.machine_accounts.iter_mut() {
+                account.id.get_or_insert_with(Uuid::new_v4);
+                if account.access_tokens.is_empty() {
+                    account.access_tokens.push(random_access_token());
+                }
+            }
+        }
+
+        self
+    }
+}
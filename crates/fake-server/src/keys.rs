@@ -0,0 +1,189 @@
+// Key material used to encrypt fixtures the same way a real organization would
+
+use std::{fmt, fs, io::Write, path::Path, str::FromStr};
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bitwarden_core::auth::AccessToken;
+use bitwarden_crypto::{EncString, KeyDecryptable, KeyEncryptable, SymmetricCryptoKey};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// The access token documented in the README. It is used when no key file is provided, so existing
+/// scripts keep working while the organization key is generated on every start.
+pub const DEFAULT_ACCESS_TOKEN: &str = "0.ec2c1d46-6a4b-4751-a310-af9601317f2d.C2IgxjjLF7qSshsbwe8JGcbM075YXw:X8vbvA0bduihIDe/qrzIQQ==";
+
+/// On-disk representation of [Keys], both values are base64/access token strings so the file can
+/// be edited by hand
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyFile {
+    organization_key: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Key material of the default organization
+pub struct Keys {
+    /// Symmetric key of the organization, every cipher stored by the server is encrypted with it
+    pub organization_key: SymmetricCryptoKey,
+    /// Access token of the default machine account
+    pub access_token: String,
+}
+
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keys")
+            .field("access_token", &self.access_token)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Keys {
+    /// A fresh organization key paired with [DEFAULT_ACCESS_TOKEN]
+    pub fn generate() -> Self {
+        Keys {
+            organization_key: SymmetricCryptoKey::make_aes256_cbc_hmac_key(),
+            access_token: DEFAULT_ACCESS_TOKEN.to_string(),
+        }
+    }
+
+    /// Loads the keys from `path`. Missing values are generated and written back, so the same
+    /// file yields the same keys on the next start.
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        let mut file: KeyFile = match path.exists() {
+            true => serde_json::from_str(&fs::read_to_string(path)?)
+                .with_context(|| format!("Invalid key file: {}", path.display()))?,
+            false => KeyFile::default(),
+        };
+
+        let organization_key = match file.organization_key.take() {
+            Some(key) => SymmetricCryptoKey::try_from(key).context("Invalid organization key")?,
+            None => SymmetricCryptoKey::make_aes256_cbc_hmac_key(),
+        };
+        let access_token = file.access_token.take().unwrap_or_else(random_access_token);
+
+        let file = KeyFile {
+            organization_key: Some(organization_key.to_base64().to_string()),
+            access_token: Some(access_token.clone()),
+        };
+        write_private_file(path, &serde_json::to_string_pretty(&file)?)
+            .with_context(|| format!("Could not write key file: {}", path.display()))?;
+
+        Ok(Keys {
+            organization_key,
+            access_token,
+        })
+    }
+}
+
+/// Replaces `path` atomically with a file that only the current user can read, as it holds key
+/// material
+fn write_private_file(path: &Path, contents: &str) -> Result<()> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    // Temporary files are created with 0600 permissions, which the renamed file keeps
+    let mut file = tempfile::NamedTempFile::new_in(directory)?;
+    file.write_all(contents.as_bytes())?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+
+    Ok(())
+}
+
+/// An access token that was issued to a machine account, together with the organization key
+/// encrypted for it
+#[derive(Debug, Clone)]
+pub struct IssuedAccessToken {
+    pub id: Uuid,
+    pub client_secret: String,
+    /// The full `0.<id>.<client secret>:<encryption key>` string that is handed to clients
+    pub token: String,
+    /// The organization key encrypted with the access token, as returned by the token endpoint
+    pub encrypted_payload: String,
+}
+
+impl IssuedAccessToken {
+    pub fn new(token: String, organization_key: &SymmetricCryptoKey) -> Result<Self> {
+        let parsed = AccessToken::from_str(&token).context("Invalid access token")?;
+
+        let payload = json!({ "encryptionKey": organization_key.to_base64() }).to_string();
+        let encrypted_payload = payload
+            .encrypt_with_key(&parsed.encryption_key)?
+            .to_string();
+
+        Ok(IssuedAccessToken {
+            id: parsed.access_token_id,
+            client_secret: parsed.client_secret,
+            token,
+            encrypted_payload,
+        })
+    }
+}
+
+/// Encrypts `plaintext` with `key`, returning the `EncString` representation
+pub fn encrypt(key: &SymmetricCryptoKey, plaintext: &str) -> Result<String> {
+    Ok(plaintext.encrypt_with_key(key)?.to_string())
+}
+
+/// Decrypts an `EncString` representation with `key`
+pub fn decrypt(key: &SymmetricCryptoKey, ciphertext: &str) -> Result<String> {
+    let enc_string = EncString::from_str(ciphertext)?;
+    Ok(enc_string.decrypt_with_key(key)?)
+}
+
+/// Creates a new organization key in its base64 representation
+pub fn random_organization_key() -> String {
+    SymmetricCryptoKey::make_aes256_cbc_hmac_key()
+        .to_base64()
+        .to_string()
+}
+
+/// Creates an access token in the `0.<id>.<client secret>:<encryption key>` format
+pub fn random_access_token() -> String {
+    let client_secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(30)
+        .map(char::from)
+        .collect();
+    let encryption_key = STANDARD.encode(rand::random::<[u8; 16]>());
+
+    format!("0.{}.{}:{}", Uuid::new_v4(), client_secret, encryption_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_or_create_keeps_the_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.json");
+
+        let created = Keys::load_or_create(&path).unwrap();
+        let loaded = Keys::load_or_create(&path).unwrap();
+
+        assert_eq!(created.access_token, loaded.access_token);
+        assert_eq!(
+            created.organization_key.to_base64().to_string(),
+            loaded.organization_key.to_base64().to_string()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.json");
+        Keys::load_or_create(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -1,38 +1,67 @@
 use axum::{
-    response::Json,
-    routing::{get, post, put},
+    middleware,
+    routing::{get, post},
     Router,
 };
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
+pub mod cassette;
+pub mod faults;
+pub mod fixtures;
+pub mod keys;
+pub mod requests;
 pub mod routes;
+pub mod state;
+mod test_server;
 
-pub fn create_app() -> Router {
+use fixtures::Fixtures;
+use keys::Keys;
+use state::AppState;
+pub use test_server::TestServer;
+
+/// Creates the app with a freshly generated organization key and the default seeded secrets and
+/// projects
+pub fn create_app() -> anyhow::Result<Router> {
+    Ok(create_app_with_state(AppState::new(Fixtures::seeded(
+        Keys::generate(),
+    ))?))
+}
+
+/// Creates the app on top of an existing state, which lets callers inspect or pre-populate it
+pub fn create_app_with_state(state: AppState) -> Router {
     Router::new()
         .route("/identity/connect/token", post(routes::auth::token))
         // secrets
-        .route("/api/secrets/{id}", get(routes::secrets::get_secret))
+        .route(
+            "/api/secrets/{id}",
+            get(routes::secrets::get_secret).put(routes::secrets::edit_secret),
+        )
         .route(
             "/api/organizations/{org_id}/secrets",
             get(routes::secrets::list_secrets).post(routes::secrets::create_secret),
         )
+        .route(
+            "/api/projects/{id}/secrets",
+            get(routes::secrets::list_secrets_by_project),
+        )
         .route(
             "/api/secrets/get-by-ids",
             post(routes::secrets::get_secrets_by_ids),
         )
-        .route("/api/secrets/{id}", put(routes::secrets::create_secret)) // we don't really have data to edit, so just treat it as create
         .route(
             "/api/organizations/{org_id}/secrets/sync",
             get(routes::secrets::sync_secrets),
         )
         .route("/api/secrets/delete", post(routes::secrets::delete_secrets))
         // projects
-        .route("/api/projects/{id}", get(routes::projects::get_project))
+        .route(
+            "/api/projects/{id}",
+            get(routes::projects::get_project).put(routes::projects::edit_project),
+        )
         .route(
             "/api/organizations/{org_id}/projects",
             get(routes::projects::list_projects).post(routes::projects::create_project),
         )
-        .route("/api/projects/{id}", put(routes::projects::create_project)) // we don't really have data to edit, so just treat it as create
         .route(
             "/api/projects/delete",
             post(routes::projects::delete_projects),
@@ -41,16 +70,43 @@ pub fn create_app() -> Router {
         .route("/help", get(routes::misc::help))
         .route("/health", get(routes::misc::health_check))
         .route("/echo", post(routes::misc::echo))
-        .fallback(fallback)
+        // admin
+        .route(
+            "/admin/faults",
+            get(routes::admin::list_faults)
+                .post(routes::admin::add_faults)
+                .delete(routes::admin::clear_faults),
+        )
+        .route("/admin/state", get(routes::admin::get_state))
+        .route(
+            "/admin/requests",
+            get(routes::admin::list_requests).delete(routes::admin::clear_requests),
+        )
+        .route("/admin/reset", post(routes::admin::reset))
+        .route(
+            "/admin/unknown-routes",
+            get(routes::admin::list_unknown_routes),
+        )
+        .route(
+            "/admin/cassette/remaining",
+            get(routes::admin::list_remaining_interactions),
+        )
+        .fallback(routes::misc::not_implemented)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            faults::inject,
+        ))
+        // wraps the faults, so recordings include injected faults
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            cassette::middleware,
+        ))
+        // outermost, so the log includes faulted and replayed requests
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            requests::middleware,
+        ))
+        .with_state(state)
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
 }
-
-async fn fallback(
-    uri: axum::http::Uri,
-    Json(body): Json<serde_json::Value>,
-) -> (axum::http::StatusCode, String) {
-    println!("Endpoint was hit but not implemented: {}", uri);
-    println!("Endpoint body: {}", body);
-    (axum::http::StatusCode::NOT_FOUND, "No route".to_string())
-}
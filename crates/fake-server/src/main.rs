@@ -1,11 +1,64 @@
-use std::env;
+use std::path::PathBuf;
 
 use anyhow::Result;
-use fake_server::create_app;
+use clap::Parser;
+use fake_server::{
+    cassette::{Cassette, Player, Recorder},
+    create_app_with_state,
+    fixtures::Fixtures,
+    keys::Keys,
+    state::AppState,
+};
 use tokio::net::TcpListener;
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+#[derive(Parser, Debug)]
+#[command(
+    name = "fake-server",
+    about = "Fake Bitwarden Secrets Manager API server"
+)]
+struct Args {
+    #[arg(
+        long,
+        env = "SM_FAKE_SERVER_PORT",
+        default_value_t = 3000,
+        help = "Port to listen on"
+    )]
+    port: u16,
+
+    #[arg(
+        long,
+        env = "SM_FAKE_SERVER_FIXTURES",
+        help = "YAML or JSON file with the organizations, projects, secrets and machine accounts to \
+                start with"
+    )]
+    fixtures: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "SM_FAKE_SERVER_KEY_FILE",
+        conflicts_with = "fixtures",
+        help = "JSON file with the organization key and access token of the default fixtures"
+    )]
+    key_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "SM_FAKE_SERVER_RECORD",
+        conflicts_with = "replay",
+        help = "Record every request and response to a JSONL cassette"
+    )]
+    record: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "SM_FAKE_SERVER_REPLAY",
+        help = "Serve responses from a JSONL cassette, failing requests that weren't recorded"
+    )]
+    replay: Option<PathBuf>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::registry()
@@ -17,15 +70,49 @@ async fn main() -> Result<()> {
         )
         .init();
 
-    let port = env::var("SM_FAKE_SERVER_PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse::<u16>()
-        .unwrap_or(3000);
+    let args = Args::parse();
 
-    let addr = format!("0.0.0.0:{}", port);
+    let addr = format!("0.0.0.0:{}", args.port);
     info!("Starting fake server on {}", addr);
 
-    let app = create_app();
+    let fixtures = match args.fixtures {
+        Some(path) => {
+            info!("Loading fixtures from {}", path.display());
+            Fixtures::load(&path)?
+        }
+        None => {
+            let keys = match args.key_file {
+                Some(path) => {
+                    info!("Loading keys from {}", path.display());
+                    Keys::load_or_create(&path)?
+                }
+                None => Keys::generate(),
+            };
+            Fixtures::seeded(keys)
+        }
+    };
+
+    let mut state = AppState::new(fixtures)?;
+    if let Some(path) = args.record {
+        info!("Recording requests to {}", path.display());
+        state = state.with_cassette(Cassette::Record(Recorder::create(&path)?));
+    } else if let Some(path) = args.replay {
+        info!("Replaying requests from {}", path.display());
+        state = state.with_cassette(Cassette::Replay(Player::load(&path)?));
+    }
+
+    for org in &state.fixtures().organizations {
+        for account in &org.machine_accounts {
+            for token in &account.access_tokens {
+                info!(
+                    "Access token for machine account '{}' in organization {}: {}",
+                    account.name, org.id, token
+                );
+            }
+        }
+    }
+
+    let app = create_app_with_state(state);
 
     let listener = TcpListener::bind(&addr).await?;
     info!("Server listening on http://{}", addr);
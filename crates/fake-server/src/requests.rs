@@ -0,0 +1,159 @@
+// Log of every request the server received, so tests can assert what a client actually sent
+
+use std::{cmp::Reverse, collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{
+    cassette::{capture, RecordedRequest},
+    state::AppState,
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoggedRequest {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub request: RecordedRequest,
+    /// Status of the response the server sent back
+    pub status: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestLog {
+    entries: Arc<Mutex<Vec<LoggedRequest>>>,
+}
+
+impl RequestLog {
+    pub async fn list(&self) -> Vec<LoggedRequest> {
+        self.entries.lock().await.clone()
+    }
+
+    pub async fn clear(&self) {
+        self.entries.lock().await.clear();
+    }
+}
+
+/// Number of requests per route the fake server doesn't implement, to find the gaps between it and
+/// the SDK. Unlike the [RequestLog] it's kept across resets.
+#[derive(Debug, Clone, Default)]
+pub struct UnknownRoutes {
+    counts: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnknownRoute {
+    pub route: String,
+    pub count: u64,
+}
+
+impl UnknownRoutes {
+    /// Counts a request, with IDs in the path replaced by `{id}` so they are grouped per route
+    pub async fn add(&self, method: &str, path: &str) {
+        let path = path
+            .split('/')
+            .map(|segment| match Uuid::parse_str(segment) {
+                Ok(_) => "{id}",
+                Err(_) => segment,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        *self
+            .counts
+            .lock()
+            .await
+            .entry(format!("{method} {path}"))
+            .or_default() += 1;
+    }
+
+    /// The unknown routes, most requested first
+    pub async fn list(&self) -> Vec<UnknownRoute> {
+        let mut routes: Vec<_> = self
+            .counts
+            .lock()
+            .await
+            .iter()
+            .map(|(route, count)| UnknownRoute {
+                route: route.clone(),
+                count: *count,
+            })
+            .collect();
+        routes.sort_by_key(|r| Reverse(r.count));
+        routes
+    }
+}
+
+/// Middleware that adds every request, except for `/admin` routes, to the [RequestLog]
+pub async fn middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if request.uri().path().starts_with("/admin") {
+        return next.run(request).await;
+    }
+
+    let timestamp = Utc::now();
+    let (recorded, request) = match capture(request).await {
+        Ok(captured) => captured,
+        Err(response) => return response,
+    };
+
+    let response = next.run(request).await;
+    state.requests().entries.lock().await.push(LoggedRequest {
+        timestamp,
+        request: recorded,
+        status: response.status().as_u16(),
+    });
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unknown_routes_group_ids() {
+        let routes = UnknownRoutes::default();
+        routes
+            .add(
+                "GET",
+                "/api/secrets/5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a01/history",
+            )
+            .await;
+        routes
+            .add(
+                "GET",
+                "/api/secrets/5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a02/history",
+            )
+            .await;
+        routes
+            .add(
+                "POST",
+                "/api/secrets/5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a02/history",
+            )
+            .await;
+        routes.add("GET", "/api/not-a-uuid").await;
+
+        let listed: Vec<_> = routes
+            .list()
+            .await
+            .into_iter()
+            .map(|r| (r.route, r.count))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("GET /api/secrets/{id}/history".to_string(), 2),
+                ("GET /api/not-a-uuid".to_string(), 1),
+                ("POST /api/secrets/{id}/history".to_string(), 1),
+            ]
+        );
+    }
+}
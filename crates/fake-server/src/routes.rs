@@ -1,8 +1,9 @@
 // This module organizes routes into separate modules by feature
 
 use axum::{
-    extract::{Path, Query},
-    response::Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
     Form,
 };
 use serde::{Deserialize, Serialize};
@@ -10,11 +11,79 @@ use serde_json::{json, Value};
 use tracing::info;
 use uuid::Uuid;
 
-/// Organization ID that is expected in the response
-const ORGANIZATION_ID: &str = "f4e44a7f-1190-432a-9d4a-af96013127cb";
+use crate::state::AppState;
+
+/// Error body in the same shape as the `ErrorResponseModel` returned by the official server
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn not_found() -> Self {
+        ApiError {
+            status: StatusCode::NOT_FOUND,
+            message: "Resource not found.".to_string(),
+        }
+    }
+
+    pub fn unauthorized() -> Self {
+        ApiError {
+            status: StatusCode::UNAUTHORIZED,
+            message: "Unauthorized.".to_string(),
+        }
+    }
+
+    pub fn forbidden() -> Self {
+        ApiError {
+            status: StatusCode::FORBIDDEN,
+            message: "Access denied.".to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "message": self.message,
+            "validationErrors": null,
+            "exceptionMessage": null,
+            "exceptionStackTrace": null,
+            "innerExceptionMessage": null,
+            "object": "error"
+        });
+
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Per-item result of the bulk delete endpoints
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BulkDeleteResponse {
+    pub id: Uuid,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListResponse<T> {
+    pub data: Vec<T>,
+}
 
 pub mod auth {
+    use std::ops::Deref;
+
+    use axum::{
+        extract::FromRequestParts,
+        http::{header, request::Parts},
+    };
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
     use super::*;
+    use crate::{fixtures::Permission, keys::IssuedAccessToken, state::MachineAccount};
+
+    /// Lifetime of the issued JWTs, in seconds
+    const EXPIRES_IN: u64 = 3600;
 
     #[derive(Debug, Deserialize, Serialize)]
     pub struct IdentityTokenPayloadResponse {
@@ -35,39 +104,151 @@ pub mod auth {
         pub password: Option<String>,
         pub scope: Option<String>,
     }
-    pub async fn token(Form(payload): Form<TokenRequest>) -> Json<IdentityTokenPayloadResponse> {
+    pub async fn token(
+        State(state): State<AppState>,
+        Form(payload): Form<TokenRequest>,
+    ) -> Result<Json<IdentityTokenPayloadResponse>, (StatusCode, Json<Value>)> {
         info!("Token request: {:?}", payload);
 
-        // Hard-coded token response for testing
+        let store = state.read().await;
+        let client_id = payload
+            .client_id
+            .as_deref()
+            .and_then(|id| Uuid::parse_str(id).ok());
+
+        let Some((account, access_token)) = client_id
+            .and_then(|id| store.access_token(id))
+            .filter(|(_, t)| payload.client_secret.as_deref() == Some(t.client_secret.as_str()))
+        else {
+            info!("Rejecting token request for client {:?}", payload.client_id);
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "invalid_client" })),
+            ));
+        };
+
         let token_response = IdentityTokenPayloadResponse {
-            access_token: "eyJhbGciOiJSUzI1NiIsImtpZCI6IjMwMURENkE1MEU4NEUxRDA5MUM4MUQzQjAwQkY5MDEwQzg1REJEOUFSUzI1NiIsInR5cCI6\
-                    ImF0K2p3dCIsIng1dCI6Ik1CM1dwUTZFNGRDUnlCMDdBTC1RRU1oZHZabyJ9.eyJuYmYiOjE2NzUxMDM3ODEsImV4cCI6MTY3NTEwNzM4MSwiaXNzIjo\
-                    iaHR0cDovL2xvY2FsaG9zdCIsImNsaWVudF9pZCI6ImVjMmMxZDQ2LTZhNGItNDc1MS1hMzEwLWFmOTYwMTMxN2YyZCIsInN1YiI6ImQzNDgwNGNhLTR\
-                    mNmMtNDM5Mi04NmI3LWFmOTYwMTMxNzVkMCIsIm9yZ2FuaXphdGlvbiI6ImY0ZTQ0YTdmLTExOTAtNDMyYS05ZDRhLWFmOTYwMTMxMjdjYiIsImp0aSI\
-                    6IjU3QUU0NzQ0MzIwNzk1RThGQkQ4MUIxNDA2RDQyNTQyIiwiaWF0IjoxNjc1MTAzNzgxLCJzY29wZSI6WyJhcGkuc2VjcmV0cyJdfQ.GRKYzqgJZHEE\
-                    ZHsJkhVZH8zjYhY3hUvM4rhdV3FU10WlCteZdKHrPIadCUh-Oz9DxIAA2HfALLhj1chL4JgwPmZgPcVS2G8gk8XeBmZXowpVWJ11TXS1gYrM9syXbv9j\
-                    0JUCdpeshH7e56WnlpVynyUwIum9hmYGZ_XJUfmGtlKLuNjYnawTwLEeR005uEjxq3qI1kti-WFnw8ciL4a6HLNulgiFw1dAvs4c7J0souShMfrnFO3g\
-                    SOHff5kKD3hBB9ynDBnJQSFYJ7dFWHIjhqs0Vj-9h0yXXCcHvu7dVGpaiNjNPxbh6YeXnY6UWcmHLDtFYsG2BWcNvVD4-VgGxXt3cMhrn7l3fSYuo32Z\
-                    Yk4Wop73XuxqF2fmfmBdZqGI1BafhENCcZw_bpPSfK2uHipfztrgYnrzwvzedz0rjFKbhDyrjzuRauX5dqVJ4ntPeT9g_I5n71gLxiP7eClyAx5RxdF6\
-                    He87NwC8i-hLBhugIvLTiDj-Sk9HvMth6zaD0ebxd56wDjq8-CMG_WcgusDqNzKFHqWNDHBXt8MLeTgZAR2rQMIMFZqFgsJlRflbig8YewmNUA9wAU74\
-                    TfxLY1foO7Xpg49vceB7C-PlvGi1VtX6F2i0tc_67lA5kWXnnKBPBUyspoIrmAUCwfms5nTTqA9xXAojMhRHAos_OdM".to_string(),
-            expires_in: 3600, // 1 hour
+            access_token: jwt(account, access_token),
+            expires_in: EXPIRES_IN,
             refresh_token: Some("fake_refresh_token_67890".to_string()),
             token_type: "Bearer".to_string(),
             scope: "api.secrets".to_string(),
-            encrypted_payload: "2.E9fE8+M/VWMfhhim1KlCbQ==|eLsHR484S/tJbIkM6spnG/HP65tj9A6Tba7kAAvUp+rYuQmGLixiOCfMsqt5OvBctDfvvr/Aes\
-                    Bu7cZimPLyOEhqEAjn52jF0eaI38XZfeOG2VJl0LOf60Wkfh3ryAMvfvLj3G4ZCNYU8sNgoC2+IQ==|lNApuCQ4Pyakfo/wwuuajWNaEX/2MW8/3rjXB/V7n+k=".to_string(),
+            encrypted_payload: access_token.encrypted_payload.clone(),
         };
 
-        Json(token_response)
+        Ok(Json(token_response))
+    }
+
+    /// Creates an unsigned JWT with the claims the SDK reads, most importantly the organization
+    fn jwt(account: &MachineAccount, access_token: &IssuedAccessToken) -> String {
+        let now = chrono::Utc::now().timestamp();
+
+        let header = json!({ "alg": "RS256", "typ": "at+jwt" });
+        let claims = json!({
+            "nbf": now,
+            "exp": now + EXPIRES_IN as i64,
+            "iss": "http://localhost",
+            "client_id": access_token.id,
+            "sub": account.id,
+            "organization": account.organization_id,
+            "jti": Uuid::new_v4().simple().to_string(),
+            "iat": now,
+            "scope": ["api.secrets"],
+        });
+
+        format!(
+            "{}.{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string()),
+            URL_SAFE_NO_PAD.encode("fake-signature"),
+        )
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Claims {
+        client_id: Uuid,
+        exp: i64,
+    }
+
+    /// The machine account making the request, identified by the bearer token issued by [token]
+    #[derive(Debug, Clone)]
+    pub struct Caller(pub MachineAccount);
+
+    impl Deref for Caller {
+        type Target = MachineAccount;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl FromRequestParts<AppState> for Caller {
+        type Rejection = ApiError;
+
+        async fn from_request_parts(
+            parts: &mut Parts,
+            state: &AppState,
+        ) -> Result<Self, Self::Rejection> {
+            let claims = parts
+                .headers
+                .get(header::AUTHORIZATION)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .and_then(|jwt| jwt.split('.').nth(1))
+                .and_then(|payload| URL_SAFE_NO_PAD.decode(payload).ok())
+                .and_then(|payload| serde_json::from_slice::<Claims>(&payload).ok())
+                .ok_or_else(ApiError::unauthorized)?;
+
+            if claims.exp < chrono::Utc::now().timestamp() {
+                return Err(ApiError::unauthorized());
+            }
+
+            // Tokens stop working as soon as the access token is removed from the store
+            let store = state.read().await;
+            let (account, _) = store
+                .access_token(claims.client_id)
+                .ok_or_else(ApiError::unauthorized)?;
+
+            Ok(Caller(account.clone()))
+        }
+    }
+
+    /// Resources in other organizations are hidden, like the official server does
+    pub fn authorize_org(caller: &Caller, organization_id: Uuid) -> Result<(), ApiError> {
+        match caller.organization_id == organization_id {
+            true => Ok(()),
+            false => Err(ApiError::not_found()),
+        }
+    }
+
+    /// Requires read access to a project, or to unassigned secrets for `None`
+    pub fn authorize_read(caller: &Caller, project_id: Option<Uuid>) -> Result<(), ApiError> {
+        match caller.can_read(project_id) {
+            true => Ok(()),
+            false => Err(ApiError::not_found()),
+        }
+    }
+
+    /// Requires write access to a project, or to unassigned secrets for `None`. Read-only access
+    /// is forbidden while no access at all hides the resource.
+    pub fn authorize_write(caller: &Caller, project_id: Option<Uuid>) -> Result<(), ApiError> {
+        match caller.permission(project_id) {
+            Some(Permission::ReadWrite) => Ok(()),
+            Some(Permission::Read) => Err(ApiError::forbidden()),
+            None => Err(ApiError::not_found()),
+        }
     }
 }
 
 pub mod secrets {
-    use bitwarden_sm::secrets::SecretResponse;
     use chrono::DateTime;
 
-    use super::*;
+    use super::{
+        auth::{authorize_org, authorize_read, authorize_write, Caller},
+        *,
+    };
+    use crate::state::{Secret, Store};
+
     #[derive(Debug, Serialize)]
     pub struct SecretsSyncResponse {
         #[serde(rename = "hasChanges")]
@@ -83,6 +264,7 @@ pub mod secrets {
 
     // bitwarden_sm::secrets::SecretCreateRequest has deny_unknown_fields
     #[derive(Debug, Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
     pub struct CreateSecretRequest {
         pub key: String,
         pub value: String,
@@ -96,8 +278,46 @@ pub mod secrets {
     }
 
     #[derive(Debug, Deserialize, Serialize)]
-    pub struct GetByIdsResponse {
-        data: Vec<SecretResponse>,
+    pub struct SecretProjectResponse {
+        pub id: Uuid,
+        pub name: String,
+    }
+
+    /// Mirrors the server's `SecretResponseModel`, the SDK reads the project from `projects`
+    #[derive(Debug, Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct SecretResponse {
+        pub id: Uuid,
+        pub organization_id: Uuid,
+        pub key: String,
+        pub value: String,
+        pub note: String,
+        pub creation_date: DateTime<chrono::Utc>,
+        pub revision_date: DateTime<chrono::Utc>,
+        pub projects: Vec<SecretProjectResponse>,
+    }
+
+    impl SecretResponse {
+        pub fn new(secret: &Secret, store: &Store) -> Self {
+            SecretResponse {
+                id: secret.id,
+                organization_id: secret.organization_id,
+                key: secret.key.clone(),
+                value: secret.value.clone(),
+                note: secret.note.clone(),
+                creation_date: secret.creation_date,
+                revision_date: secret.revision_date,
+                projects: secret
+                    .project_id
+                    .and_then(|id| store.project(id))
+                    .map(|p| SecretProjectResponse {
+                        id: p.id,
+                        name: p.name.clone(),
+                    })
+                    .into_iter()
+                    .collect(),
+            }
+        }
     }
 
     #[derive(Debug, Deserialize, Serialize)]
@@ -105,236 +325,608 @@ pub mod secrets {
         secrets: Vec<SecretResponse>,
     }
 
-    pub async fn list_secrets() -> Json<SecretListResponse> {
-        let org_id = Uuid::parse_str(ORGANIZATION_ID).unwrap();
+    pub async fn list_secrets(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(org_id): Path<Uuid>,
+    ) -> Result<Json<SecretListResponse>, ApiError> {
         info!("Listing secrets for organization: {}", org_id);
+        authorize_org(&caller, org_id)?;
 
-        let secrets = vec![
-            SecretResponse {
-                id: uuid::Uuid::new_v4(),
-                organization_id: org_id,
-                project_id: Some(uuid::Uuid::new_v4()),
-                key: "2.pMS6/icTQABtulw52pq2lg==|XXbxKxDTh+mWiN1HjH2N1w==|Q6PkuT+KX/axrgN9ubD5Ajk2YNwxQkgs3WJM0S0wtG8=".to_string(),
-                value: "2.i189LsTzTnYi00heXfe5fw==|xRFAsQGm1qbpasRBw0i9cg==|oNaTecIpkIFITxcI/pNHF8FOyuBMGgHIyS4PoLiJ34Y=".to_string(),
-                note: "2.i189LsTzTnYi00heXfe5fw==|xRFAsQGm1qbpasRBw0i9cg==|oNaTecIpkIFITxcI/pNHF8FOyuBMGgHIyS4PoLiJ34Y=".to_string(),
-                creation_date: chrono::Utc::now(),
-                revision_date: chrono::Utc::now(),
-            },
-        ];
-
-        Json(SecretListResponse { secrets })
-    }
-
-    pub async fn create_secret(Json(payload): Json<CreateSecretRequest>) -> Json<SecretResponse> {
-        let org_id = Uuid::parse_str(ORGANIZATION_ID).unwrap();
-        info!(
-            "Creating (or editing) secret for organization {}: {:?}",
-            org_id, payload
-        );
+        let store = state.read().await;
+        let secrets = store
+            .secrets
+            .iter()
+            .filter(|s| s.organization_id == org_id && caller.can_read(s.project_id))
+            .map(|s| SecretResponse::new(s, &store))
+            .collect();
+
+        Ok(Json(SecretListResponse { secrets }))
+    }
+
+    pub async fn list_secrets_by_project(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(project_id): Path<Uuid>,
+    ) -> Result<Json<SecretListResponse>, ApiError> {
+        info!("Listing secrets for project: {}", project_id);
+
+        let store = state.read().await;
+        let project = store.project(project_id).ok_or_else(ApiError::not_found)?;
+        authorize_org(&caller, project.organization_id)?;
+        authorize_read(&caller, Some(project_id))?;
+
+        let secrets = store
+            .secrets
+            .iter()
+            .filter(|s| s.project_id == Some(project_id))
+            .map(|s| SecretResponse::new(s, &store))
+            .collect();
+
+        Ok(Json(SecretListResponse { secrets }))
+    }
+
+    /// Secrets can only be put in projects of their own organization. Projects of other
+    /// organizations are hidden, like unknown ones.
+    fn authorize_project(
+        store: &Store,
+        organization_id: Uuid,
+        project_id: Option<Uuid>,
+    ) -> Result<(), ApiError> {
+        match project_id.map(|id| store.project(id)) {
+            None => Ok(()),
+            Some(Some(project)) if project.organization_id == organization_id => Ok(()),
+            Some(_) => Err(ApiError::not_found()),
+        }
+    }
 
-        let secret = SecretResponse {
+    pub async fn create_secret(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(org_id): Path<Uuid>,
+        Json(payload): Json<CreateSecretRequest>,
+    ) -> Result<Json<SecretResponse>, ApiError> {
+        info!("Creating secret for organization {}: {:?}", org_id, payload);
+        authorize_org(&caller, org_id)?;
+
+        let mut store = state.write().await;
+        let project_id = payload.project_ids.and_then(|ids| ids.first().cloned());
+        authorize_project(&store, org_id, project_id)?;
+        authorize_write(&caller, project_id)?;
+
+        let now = chrono::Utc::now();
+        let secret = Secret {
             id: Uuid::new_v4(),
             organization_id: org_id,
-            project_id: payload.project_ids.and_then(|ids| ids.first().cloned()),
+            project_id,
             key: payload.key,
             value: payload.value,
             note: payload.note,
-            creation_date: chrono::Utc::now(),
-            revision_date: chrono::Utc::now(),
+            creation_date: now,
+            revision_date: now,
         };
 
-        Json(secret)
+        let response = SecretResponse::new(&secret, &store);
+        store.secrets.push(secret);
+        store.touch(org_id, &[project_id]);
+
+        Ok(Json(response))
     }
 
-    pub async fn get_secret(Path(id): Path<Uuid>) -> Json<SecretResponse> {
-        info!("Getting secret with id: {}", id);
+    pub async fn edit_secret(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(id): Path<Uuid>,
+        Json(payload): Json<CreateSecretRequest>,
+    ) -> Result<Json<SecretResponse>, ApiError> {
+        info!("Editing secret {}: {:?}", id, payload);
 
-        let secret = SecretResponse {
-            id: id,
-            organization_id: Uuid::parse_str(ORGANIZATION_ID).unwrap(),
-            project_id: Some(uuid::Uuid::new_v4()),
-            key: "2.WYqmVCB2wZc08tkzNOCmTw==|FAsVol/nJnnDk3/mp7z6QQ==|uPJOCC8iAbMzz4t60c35iZm8KzWKMn0ueCVJZlfmTdY=".to_string(),
-            value: "2.IYOGfBMSOI5qOxfYGHd6Rg==|0PBFivy/Qtp4lg4vv1+yPn/sDeRsNWmRnUYgwmAgPzUqZA9ZojvuggVSp/isPPc2mYO5UQfb/co/81fDhQqopHrwat0l8SRB+sv/uEuomDdMkjaYl+jqblXebIDN42ZCy1wbERZgFmCMm3k1OIj1z5WHdRFGTWDLFlP316SgkAKOwaZF0eNmcQ90Py5Mrq9rKeVozsPWIL3aAXNchID6kJnqxbx717BxKQ9Vj/dMAaBlQoGrl/cYA6hoUBq7wOSMWkZ8PAorLhc3OSDwGT/iamlAfePbkbjVqlTK2WrQ5ZHIo5Qzwpd/cvn6a0rSW5cPQ6DLrrOBdgDU3ELJ3eB+vZ/IWl9jXsCQ3re6Pv4pOToAMYDYEkC7DlwbSiCWLegqbexwPNLRLa2hM9n+V8nVPgNic+LyakfsLqx1ReDFY0A7qRs7pE/EabYyj1O44HwZT3sSFKGYPlTBmQh6S21T7eGJ4+OV+dhnFSpjiJ7IjOhfAzwq8cUiAeIEvKECBD++C+TsGwNAYK57F8Dd2gEwSaDhkiEPssa/c9ZBQnarNWzmZN1gj4udXRmsXqAY6GcrZiLhBIpW2Yap8VVdgbQ9vwN77NzLfFW/FsdlAPB22dvjR1SzszgweG2QstGi9PcKY0Mp1zSvswWdGjdBpbfuExXBD62Fp+DWOmFzWPo2MyqSQLaegvO4G+v8DRlf7VHA34Yvcbzv9Jtq4+H+Z7SkglRcQvKrn9uv7qOlZPvGJs1Ri86BAopXIGsj/5XfQTdtQdhs4c0vviMSrNWtNvIgfg==|Z6BNqVlCknATGieykii0vF9xKu+JT3u2WqtbDhSYvkY=".to_string(),
-            note: "2.S57kOfi1kIHjToxwR6sEuQ==|lTop/7iWWUveCGWXrHbHwg==|YrtUfrlRRN+ff8Re7txi2dTT9Ul0cwmiFWDgVpdWGlc=".to_string(),
-            creation_date: chrono::Utc::now(),
-            revision_date: chrono::Utc::now(),
-        };
+        let mut store = state.write().await;
+        let project_id = payload.project_ids.and_then(|ids| ids.first().cloned());
+
+        let current = store.secret(id).ok_or_else(ApiError::not_found)?;
+        let (organization_id, previous_project_id) = (current.organization_id, current.project_id);
+        authorize_org(&caller, organization_id)?;
+        authorize_project(&store, organization_id, project_id)?;
+        // Moving a secret requires write access to both the old and the new project
+        authorize_write(&caller, previous_project_id)?;
+        authorize_write(&caller, project_id)?;
+
+        let secret = store.secret_mut(id).ok_or_else(ApiError::not_found)?;
+        secret.key = payload.key;
+        secret.value = payload.value;
+        secret.note = payload.note;
+        secret.project_id = project_id;
+        secret.revision_date = chrono::Utc::now();
+
+        let secret = secret.clone();
+        // Accounts that lost access to the secret by the move have to see a change too
+        store.touch(organization_id, &[previous_project_id, project_id]);
 
-        Json(secret)
+        Ok(Json(SecretResponse::new(&secret, &store)))
     }
 
-    pub async fn get_secrets_by_ids(Json(payload): Json<GetByIdsBody>) -> Json<GetByIdsResponse> {
-        let org_id = Uuid::parse_str(ORGANIZATION_ID).unwrap();
+    pub async fn get_secret(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(id): Path<Uuid>,
+    ) -> Result<Json<SecretResponse>, ApiError> {
+        info!("Getting secret with id: {}", id);
 
-        let id1 = payload.ids.get(0).cloned().unwrap_or(Uuid::new_v4());
-        let id2 = payload.ids.get(1).cloned().unwrap_or(Uuid::new_v4());
+        let store = state.read().await;
+        let secret = store.secret(id).ok_or_else(ApiError::not_found)?;
+        authorize_org(&caller, secret.organization_id)?;
+        authorize_read(&caller, secret.project_id)?;
 
-        info!("Getting secrets with ids: {}, {}", id1, id2);
+        Ok(Json(SecretResponse::new(secret, &store)))
+    }
 
-        let mut secrets: Vec<SecretResponse> = payload.ids.iter().map(|_| SecretResponse {
-            // FERRIS, the crab
-            id: id1,
-            organization_id: org_id,
-            project_id: Some(uuid::Uuid::new_v4()),
-            key: "2.N2aCz0PU6Ga9YfJlvisnwQ==|3M8dF2PFub9FP/SbgdenSQ==|KbaUQSb5IjVwhSbXDbCJbKXGBaCHEKDArrhvQDr9/QM=".to_string(), //
-            value: "2./mtIg5EsCJbesKfmbWAx+A==|i+T19OI5AjPgn3oFV3MsajUXfV3N9cTRTD3zBO7r274ShPuYHCigsHN+OW/Zml2iTPp5TFTd+lLc92oRbE88oNwVzh3wv2tp8mJIWZvIGa3HBBp6Vt7sYki6bvRECeXDlsV6Vn1F74oeaWaI0nkdXzvqgVwVJ2rEq1gQs3xYOkBVOZApQSoBsmG/vChXegVwJy5kKg2haI4QHSiw0t1IuD63KPKqmuHBwVATbxYwCDkN3lxP4LEaBDYrYsu8BcARhoOlbIH0xeq+Unf5dwwaoCeQ5jOYmd+NVkJ1urPi7GTaGjBUb6IpQjGjB6gB2HVb/VdZP5iOwKP+i5kG20ibszOsnQ2SrACwmQb0SfjKgBSUcMrXFcufkwN6wdQz7JZ9JI0Smf4wQlmC59YqTSB0oBtJ2pgWhp3RKP2sA/crJxA5+AtR6ASRFiIjaqxMIsmR84cyeTMzm/O+FR74uOVHqF48lrHpON3Zl8Amx1lOzmHlIoG/vfH4vleFDKXw5rEm6fdimLjDU7//R+pL3IrjAhlGK7CfrPI8ntwYrWo5dVW1klweRXn5OxoHlKCw+uGAkHJhteTYGext1dkvKIS1Y1yHRu7v/UDpeJKKpHPogCR3oTgBR2ixYwc4yhn+AY2JmjNUA7B13xwqP0ThpMjBfrnk4K/2e5e+9KOJPFlQJcfG+17yoqApRjHpyBRyAKt729HOGGB6O/QUOkiKTkwjRG56qpilO4s72W4A5AMSa4NPj3udh84PbT0DZg3/l4ir7zn1YPtX5TGQmkXhCzPMimYWf3fT1CkbH3s7M0yc0A4V1v5gI8Tr5b7RpNnNqVTyGL4LHXY5N+66lDFAXZNtaD0gx/J6sFIiCZ5v4W/hHWLsfvw35Bs9AMd6FgVi7hqiM6outw0sPR3BTebFROzbMkawX/rkT1b86qu+Lvk5XAYeYQMcW5ee4QKIZThHvrLrnxX5XmbnLuaiY/LjD7zsJmQ5VLK27WFn6F2E0bU3OhuRCrxFrlOxokNINecCqk1DpaOGbeSrMIxVi5E5mJTUOzDX3vToVM1Yk0981H0wv1poD5qFlsi4oATIKJ8fWwMb38b5UHChnkQ9li09IRrl4XgNUciP2zNo/8euCC1Y6aKfuL3iZQlgDls24ebd95P06hGhOFp8Zk5dL0YLGgzgoLoeocm3qNaZptzy0DA29h092NUnuMYPphh0kzCmnxhGGgPyZn3Dtz0/aWvcRSxcIqPQ8EX3auPshlGfUMslVsK6dqpzbWLF8Ej5Cue69eWZgrgqRYDyVtHnM56zFZE0Afs1XEWWbj0EIP+8nfELUkiVOq3PuVyRrQGa5hfv6oIa6JrrmNlLpSptXSEDtjiWO2ZaS+jxn427EsFUJHnO50WsxZAywcHb56Dqxi0DURIjDHo7YZ4ze747ulqkz8LboSNX1kdDuQv6CU4rVRv/HvUaBtHieEcWnq/APt+54UD4QCV9JQL4uQ2i7SHDVkb09yTgLzqYWXSsIp/2vxphbpnjJ5wQpX1xkMfHbRQX0jXHwaQG+CpYgKLmSARQ+aYR4V/AXQ5EDwhxpXIGQHI9ln/b+tHBWskr7fqQxGU6wKo3/Jv0XCxn05EY6BwoIfrCWWuZtSh9VDJYYyPuYHHgPRD+bqOuwbYs2ak8rjbMYXBhdeE6ogAY08+UuzN5AN4W0+Roex7HcwaMmPeaKXFQKM42FJw7ZahvMlXRR1t2LO8hSkQaoXTURgxuksIQc67eWxC72pceJAa/0txusahOahmwdboFj58ritVLi/8fJOQVxphSEH+TfL2cSjQOfJkPl1JmRMyoNhb/zONjaYk/Pdvxd/sqBvOvvxCLsFLv52Ux3T+XBd2JG8bDb1p0bXjafa+9piS9dMAsTXTLvpS0Lg2SGJnCtpj3dyzd0zULU8H9EZYBXn0LsuJzkqVGiv5rgGv5GG2WszwAP/JRSFSjBe/rQqzOdG6UX9nc+vaAiu7ygC4mKCh6/66zwvM5MESg5eTBTm5H4SFmqu/RoU9YO1xAumK/NiPU7GGAFcFMkLmjo2p7M8tok84wl68TAZzLgfXkOzZYntuo9qCV5XrMmvzvVubcibjPeOGM3Q4XdiPct+iLcZSGoxhenPvJglWWpIPN/lUt1aDj8k/eLyaYmpLyV9bjdqEBOD4W/hgTeoGcxEaRZszX3Q442TZjCChLRAIQkasP3ugLTB47UgSp90/nBeWNJXUlygXi4Z2ZM7K3WzjNK0N+Eq+mDbn9+0Uv4W7oc0st5KrJB94Z+oKfa/zVAdLhEfiXrriOsspLeJFJM/+rbx0L8CM3O9LMyWozlQ==|QleWp2LxrabRZ7zotbp4JSrILgQoMqmCJlpIAoHEXVE=".to_string(),
-            note: "2.pMS6/icTQABtulw52pq2lg==|XXbxKxDTh+mWiN1HjH2N1w==|Q6PkuT+KX/axrgN9ubD5Ajk2YNwxQkgs3WJM0S0wtG8=".to_string(),
-            creation_date: chrono::Utc::now(),
-            revision_date: chrono::Utc::now(),
-        }).collect::<Vec<_>>();
-
-        secrets.push(SecretResponse {
-            // TUX, the penguin
-            id: id2,
-            organization_id: org_id,
-            project_id: Some(uuid::Uuid::new_v4()),
-            key: "2.OldQj0RJKww0WN7RSxI1wQ==|TpxAbmdx6zIVo37YJ5n1aQ==|06Imyx7jqaZ5J5amrBboCVPwvPoDKB8REJdToQwp3dA=".to_string(),
-            value: "2.oEDp566lC9VYHn6XmusxfA==|Gj23w5q2NZ4z9PNne1d0ug==|y7K5TgMJFI0T0yFwLXzAMf9OBANNT567hLQ+z7G2rac=".to_string(),
-            note: "2.owktgGRm4r+ho4WY4U9zvA==|6Up5NQHyZ65SL3vbNI1GhQ==|vdvWvPpoB/J3aWXKBiruqOr1SK/ndkCCTjHf2vphhu4=".to_string(),
-            creation_date: chrono::Utc::now(),
-            revision_date: chrono::Utc::now(),
-        });
-        Json(GetByIdsResponse { data: secrets })
+    pub async fn get_secrets_by_ids(
+        State(state): State<AppState>,
+        caller: Caller,
+        Json(payload): Json<GetByIdsBody>,
+    ) -> Json<ListResponse<SecretResponse>> {
+        info!("Getting secrets with ids: {:?}", payload.ids);
+
+        let store = state.read().await;
+        let secrets = payload
+            .ids
+            .iter()
+            .filter_map(|id| store.secret(*id))
+            .filter(|s| {
+                s.organization_id == caller.organization_id && caller.can_read(s.project_id)
+            })
+            .map(|s| SecretResponse::new(s, &store))
+            .collect();
+
+        Json(ListResponse { data: secrets })
     }
 
-    pub async fn delete_secrets(Json(ids): Json<Vec<Uuid>>) -> Json<Value> {
+    pub async fn delete_secrets(
+        State(state): State<AppState>,
+        caller: Caller,
+        Json(ids): Json<Vec<Uuid>>,
+    ) -> Json<ListResponse<BulkDeleteResponse>> {
         info!("Deleting secrets with ids: {:?}", ids);
 
-        for id in &ids {
-            info!("Deleted secret with id: {}", id);
-        }
+        let mut store = state.write().await;
+        let results = ids
+            .into_iter()
+            .map(|id| {
+                let allowed = store.secret(id).map(|s| {
+                    authorize_org(&caller, s.organization_id)
+                        .and_then(|_| authorize_write(&caller, s.project_id))
+                        .map(|_| (s.organization_id, s.project_id))
+                });
 
-        Json(json!({
-            "message": "Secrets deleted successfully",
-            "deleted_ids": ids
-        }))
+                let error = match allowed {
+                    Some(Ok((organization_id, project_id))) => {
+                        store.remove_secret(id);
+                        store.touch(organization_id, &[project_id]);
+                        info!("Deleted secret with id: {}", id);
+                        None
+                    }
+                    Some(Err(e)) if e.status == StatusCode::FORBIDDEN => {
+                        Some("access denied".to_string())
+                    }
+                    _ => Some("Secret not found.".to_string()),
+                };
+                BulkDeleteResponse { id, error }
+            })
+            .collect();
+
+        Json(ListResponse { data: results })
     }
 
-    pub async fn sync_secrets(Query(params): Query<SyncQueryParams>) -> Json<SecretsSyncResponse> {
-        let org_id = Uuid::parse_str(ORGANIZATION_ID).unwrap();
+    pub async fn sync_secrets(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(org_id): Path<Uuid>,
+        Query(params): Query<SyncQueryParams>,
+    ) -> Result<Json<SecretsSyncResponse>, ApiError> {
         info!("Syncing secrets for organization: {}", org_id);
+        authorize_org(&caller, org_id)?;
 
-        if let Some(date) = params.last_synced_date {
-            if date < chrono::Utc::now() {
-                return Json(SecretsSyncResponse {
-                    has_changes: false,
-                    secrets: None,
-                });
-            }
+        // Compare against the account in the store, the caller is a snapshot from authentication
+        let store = state.read().await;
+        let revision_date = store
+            .machine_account(caller.id)
+            .map_or(caller.revision_date, |a| a.revision_date);
+
+        // Like the official server, any change returns the full list of accessible secrets so
+        // deletions can be detected by the client
+        if params
+            .last_synced_date
+            .is_some_and(|date| revision_date <= date)
+        {
+            return Ok(Json(SecretsSyncResponse {
+                has_changes: false,
+                secrets: None,
+            }));
         }
 
-        let secrets = vec![
-            SecretResponse {
-                id: uuid::Uuid::new_v4(),
-                organization_id: org_id,
-                project_id: Some(uuid::Uuid::new_v4()),
-                key: "2.4MPPDSh3oQPfK0Sxny925g==|Rj4P0P6M3l/WD99KiwtRiQ==|6wcq4L8ZJ45h549RbiVd1sI26Q6QxaUPmQB2dAXVO1c=".to_string(),
-                value: "2.78LSmLCl2XW8za0HyZUV2Q==|rwLKE0FGfyAZT9CUR9yUVg==|xZIthCfi1fzJOZ/riBQ3FXfuICBUv8jeE3Qrbg1f5D8=".to_string(),
-                note: "2.M/Sa8H76DNUT1RFT082VFg==|ILTxxg3P4w4aqkY1q18I4w==|mdHLGBghl+apZirhuBfbAWkTENKBJV4GnM03fhbHxy0=".to_string(),
-                creation_date: chrono::Utc::now(),
-                revision_date: chrono::Utc::now(),
-            },
-        ];
+        let secrets: Vec<SecretResponse> = store
+            .secrets
+            .iter()
+            .filter(|s| s.organization_id == org_id && caller.can_read(s.project_id))
+            .map(|s| SecretResponse::new(s, &store))
+            .collect();
 
         let secrets_response = serde_json::json!({
             "data": secrets
         });
 
-        Json(SecretsSyncResponse {
+        Ok(Json(SecretsSyncResponse {
             has_changes: true,
             secrets: Some(secrets_response),
-        })
+        }))
     }
 }
 
 pub mod projects {
     use bitwarden_sm::projects::{ProjectResponse, ProjectsResponse};
 
-    use super::*;
+    use super::{
+        auth::{authorize_org, authorize_read, authorize_write, Caller},
+        *,
+    };
+    use crate::{fixtures::Permission, state::Project};
 
     #[derive(Debug, Deserialize, Serialize)]
     pub struct CreateProjectRequest {
         pub name: String,
     }
 
-    pub async fn list_projects() -> Json<ProjectsResponse> {
-        let org_id = Uuid::parse_str(ORGANIZATION_ID).unwrap();
+    fn to_response(project: &Project) -> ProjectResponse {
+        ProjectResponse {
+            id: project.id,
+            organization_id: project.organization_id,
+            name: project.name.clone(),
+            creation_date: project.creation_date,
+            revision_date: project.revision_date,
+        }
+    }
+
+    pub async fn list_projects(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(org_id): Path<Uuid>,
+    ) -> Result<Json<ProjectsResponse>, ApiError> {
         info!("Listing projects for organization: {}", org_id);
+        authorize_org(&caller, org_id)?;
 
-        let projects =
-        vec![
-            ProjectResponse {
-                id: uuid::Uuid::new_v4(),
-                organization_id: org_id,
-                name: "2.DmcNJqtzi+nPWY9gJR4nMw==|IEkn2x+C0YLmnQ/qm0EfOcMGcRZDkexFkDW9BPw3wRQ=|TxxTeBKqL0QYLT+89F0KfI81BbBryXnNNAjU9DGKuuY=".to_string(),
-                creation_date: chrono::Utc::now(),
-                revision_date: chrono::Utc::now(),
-            },
-            ProjectResponse {
-                id: uuid::Uuid::new_v4(),
-                organization_id: org_id,
-                name: "2.4hWxQC9O5KpHcyCyI/xBsQ==|RLkyV/QbEMpxPnO91E/jPURCvsDIjI1ZIh6eMvGIuEg=|FXnie8Z9OtaBElnzF0v4Iut0fmy7IAI2IedEKJKuSp0=".to_string(),
-                creation_date: chrono::Utc::now(),
-                revision_date: chrono::Utc::now(),
-            }
-        ];
+        let store = state.read().await;
+        let projects = store
+            .projects
+            .iter()
+            .filter(|p| p.organization_id == org_id && caller.can_read(Some(p.id)))
+            .map(to_response)
+            .collect();
 
-        Json(ProjectsResponse { data: projects })
+        Ok(Json(ProjectsResponse { data: projects }))
     }
 
     pub async fn create_project(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(org_id): Path<Uuid>,
         Json(payload): Json<CreateProjectRequest>,
-    ) -> Json<ProjectResponse> {
-        let org_id = Uuid::parse_str(ORGANIZATION_ID).unwrap();
+    ) -> Result<Json<ProjectResponse>, ApiError> {
         info!(
-            "Creating (or editing) project for organization {}: {:?}",
+            "Creating project for organization {}: {:?}",
             org_id, payload
         );
+        authorize_org(&caller, org_id)?;
 
-        let project = bitwarden_sm::projects::ProjectResponse {
-            id: uuid::Uuid::new_v4(),
+        let now = chrono::Utc::now();
+        let project = Project {
+            id: Uuid::new_v4(),
             organization_id: org_id,
             name: payload.name,
-            creation_date: chrono::Utc::now(),
-            revision_date: chrono::Utc::now(),
+            creation_date: now,
+            revision_date: now,
         };
 
-        Json(project)
+        let response = to_response(&project);
+        let mut store = state.write().await;
+
+        // Scoped accounts get write access to the projects they create
+        if let Some(projects) = store
+            .machine_account_mut(caller.id)
+            .and_then(|a| a.projects.as_mut())
+        {
+            projects.insert(project.id, Permission::ReadWrite);
+        }
+        store.projects.push(project);
+
+        Ok(Json(response))
+    }
+
+    pub async fn edit_project(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(id): Path<Uuid>,
+        Json(payload): Json<CreateProjectRequest>,
+    ) -> Result<Json<ProjectResponse>, ApiError> {
+        info!("Editing project {}: {:?}", id, payload);
+
+        let mut store = state.write().await;
+        let project = store.project_mut(id).ok_or_else(ApiError::not_found)?;
+        authorize_org(&caller, project.organization_id)?;
+        authorize_write(&caller, Some(id))?;
+
+        project.name = payload.name;
+        project.revision_date = chrono::Utc::now();
+
+        Ok(Json(to_response(project)))
     }
 
-    pub async fn get_project(Path(id): Path<String>) -> Json<ProjectResponse> {
+    pub async fn get_project(
+        State(state): State<AppState>,
+        caller: Caller,
+        Path(id): Path<Uuid>,
+    ) -> Result<Json<ProjectResponse>, ApiError> {
         info!("Getting project with id: {}", id);
 
-        let project = ProjectResponse {
-            id: Uuid::new_v4(),
-            organization_id: Uuid::parse_str(ORGANIZATION_ID).unwrap(),
-            name: "2.DmcNJqtzi+nPWY9gJR4nMw==|IEkn2x+C0YLmnQ/qm0EfOcMGcRZDkexFkDW9BPw3wRQ=|TxxTeBKqL0QYLT+89F0KfI81BbBryXnNNAjU9DGKuuY=".to_string(),
-            creation_date: chrono::Utc::now(),
-            revision_date: chrono::Utc::now(),
-        };
+        let store = state.read().await;
+        let project = store.project(id).ok_or_else(ApiError::not_found)?;
+        authorize_org(&caller, project.organization_id)?;
+        authorize_read(&caller, Some(id))?;
 
-        Json(project)
+        Ok(Json(to_response(project)))
     }
 
-    pub async fn delete_projects(Json(ids): Json<Vec<String>>) -> Json<Value> {
+    pub async fn delete_projects(
+        State(state): State<AppState>,
+        caller: Caller,
+        Json(ids): Json<Vec<Uuid>>,
+    ) -> Json<ListResponse<BulkDeleteResponse>> {
         info!("Deleting projects with ids: {:?}", ids);
 
-        for id in &ids {
-            info!("Deleted project with id: {}", id);
+        let mut store = state.write().await;
+        let results = ids
+            .into_iter()
+            .map(|id| {
+                let allowed = store.project(id).map(|p| {
+                    authorize_org(&caller, p.organization_id)
+                        .and_then(|_| authorize_write(&caller, Some(id)))
+                        .map(|_| p.organization_id)
+                });
+
+                let error = match allowed {
+                    Some(Ok(organization_id)) => {
+                        // The project's secrets are unassigned rather than deleted
+                        store.remove_project(id);
+                        store.touch(organization_id, &[Some(id), None]);
+                        info!("Deleted project with id: {}", id);
+                        None
+                    }
+                    Some(Err(e)) if e.status == StatusCode::FORBIDDEN => {
+                        Some("access denied".to_string())
+                    }
+                    _ => Some("Project not found.".to_string()),
+                };
+                BulkDeleteResponse { id, error }
+            })
+            .collect();
+
+        Json(ListResponse { data: results })
+    }
+}
+
+pub mod admin {
+    use std::collections::HashMap;
+
+    use bitwarden_crypto::SymmetricCryptoKey;
+
+    use super::*;
+    use crate::{
+        cassette::{Cassette, Interaction},
+        faults::Fault,
+        fixtures::Permission,
+        keys::decrypt,
+        requests::{LoggedRequest, UnknownRoute},
+        state::{Project, Secret},
+    };
+
+    /// Accepts either a single fault or a list of them
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    pub enum FaultsRequest {
+        One(Fault),
+        Many(Vec<Fault>),
+    }
+
+    pub async fn list_faults(State(state): State<AppState>) -> Json<Vec<Fault>> {
+        Json(state.faults().list().await)
+    }
+
+    pub async fn add_faults(
+        State(state): State<AppState>,
+        Json(payload): Json<FaultsRequest>,
+    ) -> Json<Vec<Fault>> {
+        let faults = match payload {
+            FaultsRequest::One(fault) => vec![fault],
+            FaultsRequest::Many(faults) => faults,
+        };
+        info!("Adding faults: {:?}", faults);
+
+        state.faults().add(faults).await;
+        Json(state.faults().list().await)
+    }
+
+    pub async fn clear_faults(State(state): State<AppState>) -> StatusCode {
+        info!("Clearing all faults");
+
+        state.faults().clear().await;
+        StatusCode::NO_CONTENT
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct StateQueryParams {
+        /// Decrypt secrets and project names with the organization keys
+        #[serde(default)]
+        pub decrypt: bool,
+    }
+
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct MachineAccountState {
+        pub id: Uuid,
+        pub organization_id: Uuid,
+        pub name: String,
+        pub access_token_ids: Vec<Uuid>,
+        pub projects: Option<HashMap<Uuid, Permission>>,
+        pub revision_date: chrono::DateTime<chrono::Utc>,
+    }
+
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct StateResponse {
+        pub organizations: Vec<Uuid>,
+        pub projects: Vec<Project>,
+        pub secrets: Vec<Secret>,
+        pub machine_accounts: Vec<MachineAccountState>,
+    }
+
+    pub async fn get_state(
+        State(state): State<AppState>,
+        Query(params): Query<StateQueryParams>,
+    ) -> Result<Json<StateResponse>, ApiError> {
+        let store = state.read().await;
+        let mut response = StateResponse {
+            organizations: store.organizations.clone(),
+            projects: store.projects.clone(),
+            secrets: store.secrets.clone(),
+            machine_accounts: store
+                .machine_accounts
+                .iter()
+                .map(|a| MachineAccountState {
+                    id: a.id,
+                    organization_id: a.organization_id,
+                    name: a.name.clone(),
+                    access_token_ids: a.access_tokens.iter().map(|t| t.id).collect(),
+                    projects: a.projects.clone(),
+                    revision_date: a.revision_date,
+                })
+                .collect(),
+        };
+
+        if params.decrypt {
+            decrypt_state(&state, &mut response).map_err(|e| ApiError {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("Could not decrypt state: {e}"),
+            })?;
         }
 
-        Json(json!({
-            "message": "Projects deleted successfully",
-            "deleted_ids": ids
-        }))
+        Ok(Json(response))
+    }
+
+    /// Replaces every cipher with its plaintext, using the organization keys from the fixtures
+    fn decrypt_state(state: &AppState, response: &mut StateResponse) -> anyhow::Result<()> {
+        let key = |organization_id: Uuid| -> anyhow::Result<SymmetricCryptoKey> {
+            let key = state
+                .fixtures()
+                .organizations
+                .iter()
+                .find(|o| o.id == organization_id)
+                .and_then(|o| o.key.clone())
+                .ok_or_else(|| anyhow::anyhow!("No key for organization {organization_id}"))?;
+            Ok(SymmetricCryptoKey::try_from(key)?)
+        };
+
+        for project in response.projects.iter_mut() {
+            let key = key(project.organization_id)?;
+            project.name = decrypt(&key, &project.name)?;
+        }
+        for secret in response.secrets.iter_mut() {
+            let key = key(secret.organization_id)?;
+            secret.key = decrypt(&key, &secret.key)?;
+            secret.value = decrypt(&key, &secret.value)?;
+            secret.note = decrypt(&key, &secret.note)?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_requests(State(state): State<AppState>) -> Json<Vec<LoggedRequest>> {
+        Json(state.requests().list().await)
+    }
+
+    pub async fn clear_requests(State(state): State<AppState>) -> StatusCode {
+        info!("Clearing the request log");
+
+        state.requests().clear().await;
+        StatusCode::NO_CONTENT
+    }
+
+    pub async fn list_unknown_routes(State(state): State<AppState>) -> Json<Vec<UnknownRoute>> {
+        Json(state.unknown_routes().list().await)
+    }
+
+    /// The interactions of the replayed cassette that haven't been served yet
+    pub async fn list_remaining_interactions(
+        State(state): State<AppState>,
+    ) -> Result<Json<Vec<Interaction>>, ApiError> {
+        match state.cassette() {
+            Some(Cassette::Replay(player)) => Ok(Json(player.remaining().await)),
+            _ => Err(ApiError {
+                status: StatusCode::NOT_FOUND,
+                message: "The server isn't replaying a cassette.".to_string(),
+            }),
+        }
+    }
+
+    pub async fn reset(State(state): State<AppState>) -> Result<StatusCode, ApiError> {
+        info!("Resetting the state to the fixtures");
+
+        state.reset().await.map_err(|e| ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("Could not reset state: {e}"),
+        })?;
+        Ok(StatusCode::NO_CONTENT)
     }
 }
 
 pub mod misc {
-    use axum::response::Json;
+    use axum::{
+        body::Bytes,
+        http::{Method, Uri},
+        response::Json,
+    };
+    use tracing::warn;
 
     use super::*;
 
+    /// Fallback for every route the fake server doesn't implement. Accepts any body, or none.
+    pub async fn not_implemented(
+        State(state): State<AppState>,
+        method: Method,
+        uri: Uri,
+        body: Bytes,
+    ) -> ApiError {
+        warn!(
+            "Endpoint was hit but not implemented: {} {} query: {:?} body: {}",
+            method,
+            uri.path(),
+            uri.query(),
+            String::from_utf8_lossy(&body)
+        );
+        state
+            .unknown_routes()
+            .add(method.as_str(), uri.path())
+            .await;
+
+        ApiError {
+            status: StatusCode::NOT_FOUND,
+            message: format!(
+                "Not implemented by the fake server: {} {}",
+                method,
+                uri.path()
+            ),
+        }
+    }
+
     pub async fn health_check() -> Json<Value> {
         Json(json!({
             "status": "healthy",
@@ -374,6 +966,11 @@ pub mod misc {
                     "path": "/api/organizations/:org_id/secrets",
                     "description": "Create a new secret for an organization."
                 },
+                {
+                    "method": "GET",
+                    "path": "/api/projects/:id/secrets",
+                    "description": "List all secrets in a project."
+                },
                 {
                     "method": "POST",
                     "path": "/api/secrets/get-by-ids",
@@ -418,8 +1015,235 @@ pub mod misc {
                     "method": "POST",
                     "path": "/api/projects/delete",
                     "description": "Delete projects by their IDs."
+                },
+                {
+                    "method": "GET",
+                    "path": "/admin/faults",
+                    "description": "List the active faults."
+                },
+                {
+                    "method": "POST",
+                    "path": "/admin/faults",
+                    "description": "Add one or more faults."
+                },
+                {
+                    "method": "DELETE",
+                    "path": "/admin/faults",
+                    "description": "Remove all faults."
+                },
+                {
+                    "method": "GET",
+                    "path": "/admin/state",
+                    "description": "Dump the stored organizations, projects, secrets and machine accounts, pass ?decrypt=true for plaintext."
+                },
+                {
+                    "method": "GET",
+                    "path": "/admin/requests",
+                    "description": "List the requests received since startup or the last reset."
+                },
+                {
+                    "method": "DELETE",
+                    "path": "/admin/requests",
+                    "description": "Clear the request log."
+                },
+                {
+                    "method": "POST",
+                    "path": "/admin/reset",
+                    "description": "Reset secrets, projects, faults and the request log to the fixtures."
+                },
+                {
+                    "method": "GET",
+                    "path": "/admin/unknown-routes",
+                    "description": "Count the requests to routes the fake server doesn't implement."
+                },
+                {
+                    "method": "GET",
+                    "path": "/admin/cassette/remaining",
+                    "description": "List the interactions of the replayed cassette that haven't been served yet."
                 }
             ]
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io::Write};
+
+    use super::{
+        admin::{StateQueryParams, StateResponse},
+        auth::Caller,
+        secrets::{create_secret, edit_secret, CreateSecretRequest},
+        *,
+    };
+    use crate::{
+        cassette::{Cassette, Player},
+        fixtures::{
+            Fixtures, OrganizationFixture, Permission, ProjectFixture, MACHINE_ACCOUNT_ID,
+            ORGANIZATION_ID, PROJECT_STAGING_ID, SECRET_FERRIS_ID,
+        },
+        keys::Keys,
+    };
+
+    /// The default fixtures plus a second organization with a single project
+    async fn two_organizations() -> (AppState, Caller, Uuid) {
+        let other_project_id = Uuid::new_v4();
+        let mut fixtures = Fixtures::seeded(Keys::generate());
+        fixtures.organizations.push(OrganizationFixture {
+            id: Uuid::new_v4(),
+            key: None,
+            projects: vec![ProjectFixture {
+                id: Some(other_project_id),
+                name: "Other organization".to_string(),
+            }],
+            secrets: Vec::new(),
+            machine_accounts: Vec::new(),
+        });
+
+        let state = AppState::new(fixtures).unwrap();
+        let account = state
+            .read()
+            .await
+            .machine_account(MACHINE_ACCOUNT_ID)
+            .unwrap()
+            .clone();
+
+        (state, Caller(account), other_project_id)
+    }
+
+    #[tokio::test]
+    async fn remaining_interactions_need_a_replayed_cassette() {
+        let state = AppState::new(Fixtures::seeded(Keys::generate())).unwrap();
+        let error = admin::list_remaining_interactions(State(state))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::NOT_FOUND);
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let interaction = json!({
+            "request": { "method": "GET", "path": "/health" },
+            "response": { "status": 200 },
+        });
+        writeln!(file, "{interaction}").unwrap();
+        let player = Player::load(file.path()).unwrap();
+        let state = AppState::new(Fixtures::seeded(Keys::generate()))
+            .unwrap()
+            .with_cassette(Cassette::Replay(player));
+
+        let Json(remaining) = admin::list_remaining_interactions(State(state))
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].request.path, "/health");
+    }
+
+    #[tokio::test]
+    async fn state_dump_decrypts_with_the_organization_keys() {
+        let state = AppState::new(Fixtures::seeded(Keys::generate())).unwrap();
+        let dump =
+            |decrypt| admin::get_state(State(state.clone()), Query(StateQueryParams { decrypt }));
+
+        let Json(encrypted) = dump(false).await.unwrap();
+        let Json(decrypted) = dump(true).await.unwrap();
+
+        let ferris = |dump: &StateResponse| {
+            dump.secrets
+                .iter()
+                .find(|s| s.id == SECRET_FERRIS_ID)
+                .cloned()
+                .unwrap()
+        };
+        assert_ne!(ferris(&encrypted).key, "FERRIS");
+        assert_eq!(ferris(&decrypted).key, "FERRIS");
+        assert_eq!(ferris(&decrypted).note, "the crab");
+
+        let names: Vec<_> = decrypted.projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Production Environment", "Staging Environment"]);
+        assert_eq!(decrypted.organizations, [ORGANIZATION_ID]);
+        assert_eq!(decrypted.machine_accounts.len(), 1);
+        assert_eq!(decrypted.machine_accounts[0].access_token_ids.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reset_restores_the_fixtures() {
+        let state = AppState::new(Fixtures::seeded(Keys::generate())).unwrap();
+        state.write().await.secrets.clear();
+
+        admin::reset(State(state.clone())).await.unwrap();
+
+        let Json(dump) = admin::get_state(State(state), Query(StateQueryParams { decrypt: true }))
+            .await
+            .unwrap();
+        let keys: Vec<_> = dump.secrets.iter().map(|s| s.key.as_str()).collect();
+        assert_eq!(keys, ["FERRIS", "TUX", "btw"]);
+    }
+
+    fn secret_request(project_id: Uuid) -> Json<CreateSecretRequest> {
+        Json(CreateSecretRequest {
+            key: "key".to_string(),
+            value: "value".to_string(),
+            note: String::new(),
+            project_ids: Some(vec![project_id]),
+        })
+    }
+
+    #[tokio::test]
+    async fn create_secret_hides_projects_of_other_organizations() {
+        let (state, caller, other_project_id) = two_organizations().await;
+
+        let error = create_secret(
+            State(state.clone()),
+            caller,
+            Path(ORGANIZATION_ID),
+            secret_request(other_project_id),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.status, StatusCode::NOT_FOUND);
+        assert!(state
+            .read()
+            .await
+            .secrets
+            .iter()
+            .all(|s| s.project_id != Some(other_project_id)));
+    }
+
+    #[tokio::test]
+    async fn edit_secret_hides_projects_of_other_organizations() {
+        let (state, caller, other_project_id) = two_organizations().await;
+
+        let error = edit_secret(
+            State(state.clone()),
+            caller,
+            Path(SECRET_FERRIS_ID),
+            secret_request(other_project_id),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.status, StatusCode::NOT_FOUND);
+        let store = state.read().await;
+        assert_ne!(
+            store.secret(SECRET_FERRIS_ID).unwrap().project_id,
+            Some(other_project_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn create_secret_requires_write_access_to_the_project() {
+        let (state, mut caller, _) = two_organizations().await;
+        caller.0.projects = Some(HashMap::from([(PROJECT_STAGING_ID, Permission::Read)]));
+
+        let error = create_secret(
+            State(state),
+            caller,
+            Path(ORGANIZATION_ID),
+            secret_request(PROJECT_STAGING_ID),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.status, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,317 @@
+// In-memory storage shared by all route handlers for the lifetime of the server
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use bitwarden_crypto::SymmetricCryptoKey;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use uuid::Uuid;
+
+use crate::{
+    cassette::Cassette,
+    faults::Faults,
+    fixtures::{Fixtures, Permission},
+    keys::{encrypt, IssuedAccessToken},
+    requests::{RequestLog, UnknownRoutes},
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Secret {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub project_id: Option<Uuid>,
+    pub key: String,
+    pub value: String,
+    pub note: String,
+    pub creation_date: DateTime<Utc>,
+    pub revision_date: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Project {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub name: String,
+    pub creation_date: DateTime<Utc>,
+    pub revision_date: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MachineAccount {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub name: String,
+    pub access_tokens: Vec<IssuedAccessToken>,
+    /// Per-project permissions, `None` grants access to everything in the organization
+    pub projects: Option<HashMap<Uuid, Permission>>,
+    /// Last time a secret the account can read was created, changed, moved or deleted, compared
+    /// against `lastSyncedDate` by the sync endpoint
+    pub revision_date: DateTime<Utc>,
+}
+
+impl MachineAccount {
+    /// The account's permission on a project, or on secrets without a project for `None`
+    pub fn permission(&self, project_id: Option<Uuid>) -> Option<Permission> {
+        match (&self.projects, project_id) {
+            (None, _) => Some(Permission::ReadWrite),
+            (Some(projects), Some(id)) => projects.get(&id).copied(),
+            (Some(_), None) => None,
+        }
+    }
+
+    pub fn can_read(&self, project_id: Option<Uuid>) -> bool {
+        self.permission(project_id).is_some()
+    }
+}
+
+/// Organizations, secrets, projects and machine accounts, kept in insertion order so listings are
+/// stable
+#[derive(Debug, Default)]
+pub struct Store {
+    pub organizations: Vec<Uuid>,
+    pub secrets: Vec<Secret>,
+    pub projects: Vec<Project>,
+    pub machine_accounts: Vec<MachineAccount>,
+}
+
+impl Store {
+    /// Creates a store from plaintext fixtures, encrypting them with each organization's key
+    pub fn from_fixtures(fixtures: &Fixtures) -> Result<Self> {
+        let now = Utc::now();
+        let mut store = Store::default();
+
+        for org in &fixtures.organizations {
+            let key = match &org.key {
+                Some(key) => SymmetricCryptoKey::try_from(key.clone())
+                    .with_context(|| format!("Invalid key for organization {}", org.id))?,
+                None => SymmetricCryptoKey::make_aes256_cbc_hmac_key(),
+            };
+
+            for project in &org.projects {
+                store.projects.push(Project {
+                    id: project.id.unwrap_or_else(Uuid::new_v4),
+                    organization_id: org.id,
+                    name: encrypt(&key, &project.name)?,
+                    creation_date: now,
+                    revision_date: now,
+                });
+            }
+
+            for secret in &org.secrets {
+                if let Some(project_id) = secret.project_id {
+                    if !org.projects.iter().any(|p| p.id == Some(project_id)) {
+                        bail!(
+                            "Secret '{}' references unknown project {}",
+                            secret.key,
+                            project_id
+                        );
+                    }
+                }
+
+                store.secrets.push(Secret {
+                    id: secret.id.unwrap_or_else(Uuid::new_v4),
+                    organization_id: org.id,
+                    project_id: secret.project_id,
+                    key: encrypt(&key, &secret.key)?,
+                    value: encrypt(&key, &secret.value)?,
+                    note: encrypt(&key, &secret.note)?,
+                    creation_date: now,
+                    revision_date: now,
+                });
+            }
+
+            for account in &org.machine_accounts {
+                let access_tokens = account
+                    .access_tokens
+                    .iter()
+                    .map(|token| IssuedAccessToken::new(token.clone(), &key))
+                    .collect::<Result<_>>()?;
+
+                let projects = match &account.projects {
+                    Some(projects) => {
+                        if let Some(unknown) = projects
+                            .iter()
+                            .find(|a| !org.projects.iter().any(|p| p.id == Some(a.id)))
+                        {
+                            bail!(
+                                "Machine account '{}' references unknown project {}",
+                                account.name,
+                                unknown.id
+                            );
+                        }
+                        Some(projects.iter().map(|a| (a.id, a.permission)).collect())
+                    }
+                    None => None,
+                };
+
+                store.machine_accounts.push(MachineAccount {
+                    id: account.id.unwrap_or_else(Uuid::new_v4),
+                    organization_id: org.id,
+                    name: account.name.clone(),
+                    access_tokens,
+                    projects,
+                    revision_date: now,
+                });
+            }
+
+            store.organizations.push(org.id);
+        }
+
+        Ok(store)
+    }
+
+    pub fn secret(&self, id: Uuid) -> Option<&Secret> {
+        self.secrets.iter().find(|s| s.id == id)
+    }
+
+    pub fn secret_mut(&mut self, id: Uuid) -> Option<&mut Secret> {
+        self.secrets.iter_mut().find(|s| s.id == id)
+    }
+
+    pub fn project(&self, id: Uuid) -> Option<&Project> {
+        self.projects.iter().find(|p| p.id == id)
+    }
+
+    pub fn project_mut(&mut self, id: Uuid) -> Option<&mut Project> {
+        self.projects.iter_mut().find(|p| p.id == id)
+    }
+
+    /// Finds an access token by its ID (the `client_id` used to log in) and the account it
+    /// belongs to
+    pub fn access_token(&self, id: Uuid) -> Option<(&MachineAccount, &IssuedAccessToken)> {
+        self.machine_accounts.iter().find_map(|account| {
+            account
+                .access_tokens
+                .iter()
+                .find(|t| t.id == id)
+                .map(|t| (account, t))
+        })
+    }
+
+    pub fn machine_account(&self, id: Uuid) -> Option<&MachineAccount> {
+        self.machine_accounts.iter().find(|a| a.id == id)
+    }
+
+    pub fn machine_account_mut(&mut self, id: Uuid) -> Option<&mut MachineAccount> {
+        self.machine_accounts.iter_mut().find(|a| a.id == id)
+    }
+
+    /// Records a change to secrets in any of `project_ids` (`None` for secrets without a project),
+    /// bumping the revision date of every machine account that can read them
+    pub fn touch(&mut self, organization_id: Uuid, project_ids: &[Option<Uuid>]) {
+        let now = Utc::now();
+        for account in self
+            .machine_accounts
+            .iter_mut()
+            .filter(|a| a.organization_id == organization_id)
+        {
+            if project_ids.iter().any(|id| account.can_read(*id)) {
+                account.revision_date = now;
+            }
+        }
+    }
+
+    /// Removes a secret, returning `false` if it didn't exist
+    pub fn remove_secret(&mut self, id: Uuid) -> bool {
+        let count = self.secrets.len();
+        self.secrets.retain(|s| s.id != id);
+        self.secrets.len() != count
+    }
+
+    /// Removes a project and unassigns any secrets that belonged to it, returning `false` if it
+    /// didn't exist
+    pub fn remove_project(&mut self, id: Uuid) -> bool {
+        let count = self.projects.len();
+        self.projects.retain(|p| p.id != id);
+        if self.projects.len() == count {
+            return false;
+        }
+
+        for secret in self.secrets.iter_mut() {
+            if secret.project_id == Some(id) {
+                secret.project_id = None;
+            }
+        }
+
+        true
+    }
+}
+
+/// Handle to the [Fixtures], [Store], [Faults], [RequestLog] and [Cassette] that is cloned into
+/// every request
+#[derive(Debug, Clone)]
+pub struct AppState {
+    fixtures: Arc<Fixtures>,
+    store: Arc<RwLock<Store>>,
+    faults: Faults,
+    requests: RequestLog,
+    unknown_routes: UnknownRoutes,
+    cassette: Option<Cassette>,
+}
+
+impl AppState {
+    pub fn new(fixtures: Fixtures) -> Result<Self> {
+        let fixtures = fixtures.resolved();
+        let store = Store::from_fixtures(&fixtures)?;
+
+        Ok(AppState {
+            faults: Faults::new(fixtures.faults.clone()),
+            fixtures: Arc::new(fixtures),
+            store: Arc::new(RwLock::new(store)),
+            requests: RequestLog::default(),
+            unknown_routes: UnknownRoutes::default(),
+            cassette: None,
+        })
+    }
+
+    /// Records every request to, or replays every request from, `cassette`
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// The fixtures the store was created from, with all generated values filled in
+    pub fn fixtures(&self) -> &Fixtures {
+        &self.fixtures
+    }
+
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    pub fn requests(&self) -> &RequestLog {
+        &self.requests
+    }
+
+    pub fn unknown_routes(&self) -> &UnknownRoutes {
+        &self.unknown_routes
+    }
+
+    pub fn cassette(&self) -> Option<&Cassette> {
+        self.cassette.as_ref()
+    }
+
+    pub async fn read(&self) -> RwLockReadGuard<'_, Store> {
+        self.store.read().await
+    }
+
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Store> {
+        self.store.write().await
+    }
+
+    /// Puts the store, faults and request log back to how they were at startup
+    pub async fn reset(&self) -> Result<()> {
+        let store = Store::from_fixtures(&self.fixtures)?;
+        *self.store.write().await = store;
+
+        self.faults.clear().await;
+        self.faults.add(self.fixtures.faults.clone()).await;
+        self.requests.clear().await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+// Helper for running the fake server inside Rust tests
+
+use std::net::SocketAddr;
+
+use anyhow::{Context, Result};
+use bitwarden_core::ClientSettings;
+use tokio::{net::TcpListener, task::JoinHandle};
+use uuid::Uuid;
+
+use crate::{create_app_with_state, fixtures::Fixtures, keys::Keys, state::AppState};
+
+/// A fake server listening on an ephemeral localhost port. It's shut down when dropped.
+#[derive(Debug)]
+pub struct TestServer {
+    addr: SocketAddr,
+    organization_id: Uuid,
+    access_token: String,
+    state: AppState,
+    handle: JoinHandle<()>,
+}
+
+impl TestServer {
+    /// Starts a server with the default fixtures and a freshly generated organization key
+    pub async fn start() -> Result<Self> {
+        Self::start_with_state(AppState::new(Fixtures::seeded(Keys::generate()))?).await
+    }
+
+    /// Starts a server on top of an existing state. The first access token of the first
+    /// organization's first machine account is handed out by [TestServer::access_token].
+    pub async fn start_with_state(state: AppState) -> Result<Self> {
+        let (organization_id, access_token) = state
+            .fixtures()
+            .organizations
+            .first()
+            .and_then(|org| {
+                org.machine_accounts
+                    .first()
+                    .and_then(|a| a.access_tokens.first())
+                    .map(|token| (org.id, token.clone()))
+            })
+            .context("The fixtures need an organization with a machine account")?;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let app = create_app_with_state(state.clone());
+        let handle = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("Fake server stopped: {:?}", e);
+            }
+        });
+
+        Ok(TestServer {
+            addr,
+            organization_id,
+            access_token,
+            state,
+            handle,
+        })
+    }
+
+    /// `http://127.0.0.1:<port>`, the equivalent of `BWS_SERVER_URL`
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn api_url(&self) -> String {
+        format!("{}/api", self.base_url())
+    }
+
+    pub fn identity_url(&self) -> String {
+        format!("{}/identity", self.base_url())
+    }
+
+    /// Settings for an SDK client that talks to this server
+    pub fn client_settings(&self) -> ClientSettings {
+        ClientSettings {
+            identity_url: self.identity_url(),
+            api_url: self.api_url(),
+            ..Default::default()
+        }
+    }
+
+    /// An access token that can log in to [TestServer::organization_id]
+    pub fn access_token(&self) -> &str {
+        &self.access_token
+    }
+
+    pub fn organization_id(&self) -> Uuid {
+        self.organization_id
+    }
+
+    /// The server's state, to inspect or modify it directly from the test
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Stops the server and waits until it no longer accepts connections. Dropping the server
+    /// stops it too, but without waiting.
+    pub async fn shutdown(mut self) {
+        self.handle.abort();
+        // Aborting resolves the handle once the task, and with it the listener, is dropped
+        let _ = (&mut self.handle).await;
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
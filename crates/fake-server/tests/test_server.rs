@@ -0,0 +1,56 @@
+use bitwarden::{
+    auth::login::AccessTokenLoginRequest,
+    secrets_manager::{
+        secrets::{SecretGetRequest, SecretIdentifiersRequest},
+        ClientSecretsExt,
+    },
+    Client,
+};
+use fake_server::{fixtures::SECRET_BTW_ID, TestServer};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn sdk_client_reads_the_seeded_secrets() {
+    let server = TestServer::start().await.unwrap();
+
+    let client = Client::new(Some(server.client_settings()));
+    client
+        .auth()
+        .login_access_token(&AccessTokenLoginRequest {
+            access_token: server.access_token().to_string(),
+            state_file: None,
+        })
+        .await
+        .unwrap();
+
+    let secrets = client
+        .secrets()
+        .list(&SecretIdentifiersRequest {
+            organization_id: server.organization_id(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(secrets.data.len(), 3);
+
+    let secret = client
+        .secrets()
+        .get(&SecretGetRequest { id: SECRET_BTW_ID })
+        .await
+        .unwrap();
+    assert_eq!(secret.key, "btw");
+    assert_eq!(secret.value, "I use Arch, btw");
+
+    let paths: Vec<_> = server
+        .state()
+        .requests()
+        .list()
+        .await
+        .into_iter()
+        .map(|r| r.request.path)
+        .collect();
+    assert_eq!(paths[0], "/identity/connect/token");
+
+    let addr = server.base_url().replace("http://", "");
+    server.shutdown().await;
+    assert!(TcpStream::connect(addr).await.is_err());
+}
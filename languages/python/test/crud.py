@@ -1,5 +1,4 @@
 import logging
-import uuid
 import os
 import sys
 from datetime import datetime, timezone
@@ -23,6 +22,13 @@ client = BitwardenClient(
 
 organization_id = os.getenv("ORGANIZATION_ID")
 
+# IDs of the fixtures seeded by the fake server
+project_id = "2c8e3e8a-7d3b-4a8f-9b61-1f0b6f4e3a01"
+empty_project_id = "2c8e3e8a-7d3b-4a8f-9b61-1f0b6f4e3a02"
+ferris_id = "5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a01"
+tux_id = "5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a02"
+btw_id = "5b7f9a4c-3e2d-4c1b-8a09-7e6d5c4b3a03"
+
 # Note: the path must exist, the file will be created & managed by the sdk
 state_path = os.getenv("STATE_FILE")
 
@@ -52,7 +58,7 @@ def secrets():
         return secrets_list.data.data
 
     def test_secret_get():
-        secret = client.secrets().get(uuid.uuid4())
+        secret = client.secrets().get(btw_id)
         return secret.data.key == "btw"
 
     def test_secret_create():
@@ -66,17 +72,22 @@ def secrets():
         return "secret-key" in secret.data.key
 
     def test_secret_edit():
-        secret = client.secrets().create(
+        secret = client.secrets().update(
             organization_id,
+            tux_id,
             "something-new",
             "new-value",
             "updated note",
-            [uuid.uuid4()],
+            [project_id],
         )
-        return "something-new" in secret.data.key
+        if "something-new" not in secret.data.key:
+            return False
+
+        # the edit must be visible to later requests
+        return client.secrets().get(tux_id).data.note == "updated note"
 
     def test_secret_get_by_ids():
-        secrets_retrieved = client.secrets().get_by_ids([uuid.uuid4(), uuid.uuid4(), uuid.uuid4()])
+        secrets_retrieved = client.secrets().get_by_ids([ferris_id, tux_id, btw_id])
         return secrets_retrieved.data.data[0].key == "FERRIS"
 
     def test_secret_sync():
@@ -92,13 +103,18 @@ def secrets():
             # this should fail because there should NOT be changes
             return False
 
-        return True
+        # moving a secret to another project is a change
+        client.secrets().update(
+            organization_id, btw_id, "btw", "I use Arch, btw", "", [empty_project_id]
+        )
+        sync_response = client.secrets().sync(organization_id, last_synced_date)
+        return sync_response.data.has_changes is True
 
 
 
     def test_secret_delete():
-        result = client.secrets().delete([uuid.uuid4(), uuid.uuid4(), uuid.uuid4()])
-        return result.success is True
+        result = client.secrets().delete([ferris_id, tux_id, btw_id])
+        return result.success is True and all(r.error is None for r in result.data.data)
 
     run_test("secret list", test_secret_list)
     run_test("secret get", test_secret_get)
@@ -115,7 +131,7 @@ def projects():
         return projects_list.data.data[0].name == "Production Environment"
 
     def test_project_get():
-        project = client.projects().get(uuid.uuid4())
+        project = client.projects().get(project_id)
         return project.data.name == "Production Environment"
 
     def test_project_create():
@@ -125,13 +141,13 @@ def projects():
     def test_project_edit():
         updated = client.projects().update(
             organization_id,
-            uuid.uuid4(),
+            empty_project_id,
             "new-project-name"
         )
         return "new-project-name" in updated.data.name
 
     def test_project_delete():
-        result = client.projects().delete([uuid.uuid4(), uuid.uuid4()])
+        result = client.projects().delete([project_id, empty_project_id])
         return result.success is True
 
     run_test("project list", test_project_list)